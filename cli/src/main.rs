@@ -13,8 +13,16 @@ use x509_cert::Certificate;
 use zar::rsa::pkcs1::DecodeRsaPrivateKey;
 
 #[derive(Parser)]
-#[clap(arg_required_else_help = true, about = "XAR archiver and extractor")]
+#[clap(
+    arg_required_else_help = true,
+    disable_help_flag = true,
+    about = "XAR archiver and extractor"
+)]
 struct Args {
+    /// Print help.
+    #[arg(long = "help", action = clap::ArgAction::Help)]
+    help: Option<bool>,
+
     /// Create an archive.
     #[arg(short = 'c')]
     create: bool,
@@ -82,10 +90,44 @@ struct Args {
     #[arg(long = "preserve-mtime", default_value = "true")]
     preserve_mtime: bool,
 
+    /// Preserve files' last access time.
+    #[arg(long = "preserve-atime")]
+    preserve_atime: bool,
+
+    /// Ignore archived permissions and use default permissions modified by the process umask.
+    #[arg(long = "no-same-permissions")]
+    no_same_permissions: bool,
+
+    /// Keep setuid and setgid bits of the archived permissions.
+    ///
+    /// The setgid bit of directories is always kept.
+    #[arg(long = "keep-setuid")]
+    keep_setuid: bool,
+
     /// Preserve files' owner.
     #[arg(long = "preserve-owner", action = clap::ArgAction::SetTrue)]
     preserve_owner: Option<bool>,
 
+    /// Use the specified owner for all files.
+    ///
+    /// The owner is either `NAME`, `NAME:UID` or `UID`.
+    /// The name is mapped to the local user id if the id is not specified.
+    /// Implies `--preserve-owner` on extraction.
+    #[arg(long = "owner", value_name = "OWNER")]
+    owner: Option<String>,
+
+    /// Use the specified group for all files.
+    ///
+    /// The group is either `NAME`, `NAME:GID` or `GID`.
+    /// The name is mapped to the local group id if the id is not specified.
+    /// Implies `--preserve-owner` on extraction.
+    #[arg(long = "group", value_name = "GROUP")]
+    group: Option<String>,
+
+    /// Do not record user and group names in the archive and ignore them on extraction.
+    #[arg(long = "numeric-owner")]
+    numeric_owner: bool,
+
     /// Verify table of contents' checksum.
     #[arg(long = "check-toc", default_value = "true")]
     check_toc: bool,
@@ -94,7 +136,84 @@ struct Args {
     #[arg(long = "check-files", default_value = "true")]
     check_files: bool,
 
-    /// Files.
+    /// Verify files' checksums after decompression.
+    #[arg(long = "check-extracted-files", default_value = "true")]
+    check_extracted_files: bool,
+
+    /// Archive and restore extended attributes.
+    #[arg(long = "xattrs", default_value = "true")]
+    xattrs: bool,
+
+    /// Exclude extended attributes matching the glob pattern.
+    ///
+    /// The argument can be repeated to exclude multiple patterns.
+    #[arg(long = "xattrs-exclude", value_name = "PATTERN")]
+    xattrs_exclude: Vec<String>,
+
+    /// Restore POSIX ACLs on extraction.
+    #[arg(long = "acls")]
+    acls: bool,
+
+    /// Restore file flags (e.g. immutable, append-only, no-dump) on extraction.
+    #[arg(long = "file-flags")]
+    file_flags: bool,
+
+    /// Exclude files matching the glob pattern.
+    ///
+    /// Patterns without `/` are matched against every path component, e.g. `.git` or `*.o`.
+    /// The argument can be repeated to exclude multiple patterns.
+    #[arg(long = "exclude", value_name = "PATTERN")]
+    exclude: Vec<String>,
+
+    /// Archive the files that symbolic links point to instead of the links themselves.
+    #[arg(short = 'h', long = "dereference")]
+    dereference: bool,
+
+    /// Do not descend into directories on other file systems.
+    ///
+    /// Mount points are archived as empty directories.
+    #[arg(long = "one-file-system")]
+    one_file_system: bool,
+
+    /// Produce byte-identical archives from identical input.
+    ///
+    /// Sorts the files by name, clamps timestamps to `SOURCE_DATE_EPOCH` and normalizes inode
+    /// and device numbers.
+    #[arg(long = "reproducible")]
+    reproducible: bool,
+
+    /// Archive timestamps with sub-second precision.
+    ///
+    /// Other xar implementations only support whole-second timestamps.
+    #[arg(long = "sub-second-timestamps")]
+    sub_second_timestamps: bool,
+
+    /// Do not strip leading `/` from file names and allow `..` in file names on extraction.
+    #[arg(short = 'P', long = "absolute-names")]
+    absolute_names: bool,
+
+    /// Fail if the file being extracted already exists.
+    #[arg(short = 'k', long = "keep-old-files")]
+    keep_old_files: bool,
+
+    /// Do not replace existing files on extraction.
+    #[arg(long = "skip-old-files")]
+    skip_old_files: bool,
+
+    /// Do not replace existing files that are newer than the archived ones on extraction.
+    #[arg(long = "keep-newer-files")]
+    keep_newer_files: bool,
+
+    /// Remove existing files before extracting the archived ones (the default).
+    #[arg(short = 'U', long = "unlink-first", alias = "overwrite")]
+    unlink_first: bool,
+
+    /// Remove existing non-empty directories with their contents before extracting the
+    /// archived files with the same names.
+    #[arg(long = "recursive-unlink")]
+    recursive_unlink: bool,
+
+    /// Files to archive on creation or archive members to extract on extraction.
     #[arg(
         trailing_var_arg = true,
         allow_hyphen_values = true,
@@ -133,11 +252,57 @@ impl Args {
             (..) => Err(Error::other("conflicting compression codecs specified")),
         }
     }
+
+    fn xattr_filter(&self) -> Result<zar::XattrFilter, Error> {
+        self.xattrs_exclude
+            .iter()
+            .try_fold(zar::XattrFilter::new(), |filter, pattern| {
+                filter.exclude(pattern)
+            })
+    }
+
+    fn path_filter(&self) -> Result<zar::PathFilter, Error> {
+        self.exclude
+            .iter()
+            .try_fold(zar::PathFilter::new(), |filter, pattern| {
+                filter.exclude(pattern)
+            })
+    }
+
+    fn owner_map(&self) -> Result<zar::OwnerMap, Error> {
+        let mut map = zar::OwnerMap::new();
+        if let Some(owner) = self.owner.as_ref() {
+            let (uid, name) = parse_owner(owner, zar::user_id)?;
+            map = map.user(uid, name);
+        }
+        if let Some(group) = self.group.as_ref() {
+            let (gid, name) = parse_owner(group, zar::group_id)?;
+            map = map.group(gid, name);
+        }
+        Ok(map)
+    }
+
+    fn overwrite(&self) -> Result<zar::Overwrite, Error> {
+        const T: bool = true;
+        const F: bool = false;
+        match (
+            self.keep_old_files,
+            self.skip_old_files,
+            self.keep_newer_files,
+            self.unlink_first,
+        ) {
+            (T, F, F, F) => Ok(zar::Overwrite::Error),
+            (F, T, F, F) => Ok(zar::Overwrite::Skip),
+            (F, F, T, F) => Ok(zar::Overwrite::KeepNewer),
+            (F, F, F, _) => Ok(zar::Overwrite::Replace),
+            (..) => Err(Error::other("conflicting overwrite options specified")),
+        }
+    }
 }
 
 fn main() -> ExitCode {
     match do_main() {
-        Ok(_) => ExitCode::SUCCESS,
+        Ok(code) => code,
         Err(e) => {
             eprintln!("{e}");
             ExitCode::FAILURE
@@ -160,7 +325,16 @@ fn create(args: Args) -> Result<ExitCode, Error> {
     let toc_checksum_algo: zar::ChecksumAlgo = args.toc_checksum.into();
     let options = zar::BuilderOptions::new()
         .toc_checksum_algo(toc_checksum_algo)
-        .file_checksum_algo(args.file_checksum.into());
+        .file_checksum_algo(args.file_checksum.into())
+        .xattrs(args.xattrs)
+        .xattr_filter(args.xattr_filter()?)
+        .numeric_owner(args.numeric_owner)
+        .owner_map(args.owner_map()?)
+        .follow_symlinks(args.dereference)
+        .cross_device(!args.one_file_system)
+        .path_filter(args.path_filter()?)
+        .reproducible(args.reproducible)
+        .sub_second_timestamps(args.sub_second_timestamps);
     let mut builder = match args.signing_key_file {
         Some(ref signing_key_file) => {
             let signing_key_bytes = std::fs::read(signing_key_file)?;
@@ -189,14 +363,17 @@ fn create(args: Args) -> Result<ExitCode, Error> {
 }
 
 fn extract(args: Args) -> Result<ExitCode, Error> {
-    if args.paths.len() > 1 {
-        return Err(Error::other("multiple output directories specified"));
-    }
-    let dest_dir = args
+    let dest_dir = args.chdir.as_deref().unwrap_or(Path::new("."));
+    let filter = args.paths.iter().fold(args.path_filter()?, |filter, path| {
+        filter.include_path(path)
+    });
+    // track the members that were found in the archive
+    let members: Vec<_> = args
         .paths
-        .first()
-        .map(|x| x.as_path())
-        .unwrap_or(Path::new("."));
+        .iter()
+        .map(|path| zar::PathFilter::new().include_path(path))
+        .collect();
+    let mut found = vec![false; members.len()];
     let file = File::open(&args.file_name)?;
     let (verifier, verify) = {
         let mut certs = Vec::new();
@@ -209,12 +386,41 @@ fn extract(args: Args) -> Result<ExitCode, Error> {
     let options = zar::ArchiveOptions::new()
         .check_toc(args.check_toc)
         .check_files(args.check_files)
+        .check_extracted_files(args.check_extracted_files)
         .preserve_mtime(args.preserve_mtime)
-        .preserve_owner(args.preserve_owner.unwrap_or_else(can_chown))
+        .preserve_atime(args.preserve_atime)
+        .same_permissions(!args.no_same_permissions)
+        .keep_setuid(args.keep_setuid)
+        .umask(if args.no_same_permissions { umask() } else { 0 })
+        .preserve_owner(
+            args.preserve_owner
+                .unwrap_or_else(|| args.owner.is_some() || args.group.is_some() || can_chown()),
+        )
+        .numeric_owner(args.numeric_owner)
+        .owner_map(args.owner_map()?)
+        .sanitize_paths(!args.absolute_names)
+        .overwrite(args.overwrite()?)
+        .recursive_unlink(args.recursive_unlink)
+        .xattrs(args.xattrs)
+        .xattr_filter(args.xattr_filter()?)
+        .acls(args.acls)
+        .file_flags(args.file_flags)
         .verify(verify);
     let archive = zar::Archive::with_root_cert_verifier(file, &verifier, options)?;
-    archive.extract(dest_dir)?;
-    Ok(ExitCode::SUCCESS)
+    archive.extract_filtered(dest_dir, |file| {
+        for (member, found) in members.iter().zip(found.iter_mut()) {
+            *found |= member.matches(&file.name);
+        }
+        filter.matches(&file.name)
+    })?;
+    let mut code = ExitCode::SUCCESS;
+    for (path, found) in args.paths.iter().zip(found) {
+        if !found {
+            eprintln!("{}: Not found in archive", path.display());
+            code = ExitCode::FAILURE;
+        }
+    }
+    Ok(code)
 }
 
 fn list(_args: Args) -> Result<ExitCode, Error> {
@@ -303,6 +509,34 @@ fn can_chown() -> bool {
     has_cap(None, CapSet::Permitted, Capability::CAP_CHOWN).unwrap_or(false)
 }
 
+/// Parse `NAME`, `NAME:ID` or `ID`.
+fn parse_owner(
+    s: &str,
+    lookup: fn(&str) -> Result<Option<u32>, Error>,
+) -> Result<(u32, Option<String>), Error> {
+    let invalid = || Error::other(format!("invalid owner: {s}"));
+    match s.split_once(':') {
+        Some((name, id)) => {
+            let id = id.parse().map_err(|_| invalid())?;
+            Ok((id, (!name.is_empty()).then(|| name.into())))
+        }
+        None => match s.parse() {
+            Ok(id) => Ok((id, None)),
+            Err(_) => {
+                let id = lookup(s)?.ok_or_else(|| Error::other(format!("unknown owner: {s}")))?;
+                Ok((id, Some(s.into())))
+            }
+        },
+    }
+}
+
+fn umask() -> u32 {
+    // the only way to get the current umask is to set a new one
+    let mask = unsafe { libc::umask(0o022) };
+    unsafe { libc::umask(mask) };
+    mask as u32
+}
+
 #[cfg(not(target_os = "linux"))]
 fn can_chown() -> bool {
     let uid = unsafe { libc::getuid() };
@@ -72,6 +72,7 @@ fn sign_verify() {
             .arg(&cert_pem)
             .arg("-xf")
             .arg(&archive_xar)
+            .arg("-C")
             .arg(&unpack_dir)
             .status()
             .unwrap()
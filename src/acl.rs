@@ -0,0 +1,348 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::group_id;
+use crate::user_id;
+
+/// POSIX access control list.
+///
+/// The text form is the same as the one used by `getfacl` and `setfacl`, e.g.
+/// `user::rw-,user:1000:r--,group:staff:r--,mask::r--,other::---`.
+/// The qualifiers are either numeric ids or user and group names. The names are mapped to local
+/// ids when the ACL is converted to the extended attribute value.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct Acl {
+    entries: Vec<AclEntry>,
+}
+
+impl Acl {
+    /// Create new ACL from the entries.
+    ///
+    /// The entries are sorted in canonical order.
+    pub fn new(mut entries: Vec<AclEntry>) -> Self {
+        entries.sort_unstable();
+        Self { entries }
+    }
+
+    /// Get ACL entries.
+    pub fn entries(&self) -> &[AclEntry] {
+        &self.entries[..]
+    }
+
+    /// Parse Linux `system.posix_acl_access`/`system.posix_acl_default` extended attribute value.
+    pub fn from_xattr(value: &[u8]) -> Result<Self, Error> {
+        let version = value.get(..4).ok_or(ErrorKind::InvalidData)?;
+        let value = &value[4..];
+        if u32::from_le_bytes([version[0], version[1], version[2], version[3]]) != XATTR_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "unsupported acl version",
+            ));
+        }
+        if !value.len().is_multiple_of(XATTR_ENTRY_LEN) {
+            return Err(ErrorKind::InvalidData.into());
+        }
+        let mut entries = Vec::with_capacity(value.len() / XATTR_ENTRY_LEN);
+        for entry in value.chunks_exact(XATTR_ENTRY_LEN) {
+            let tag = u16::from_le_bytes([entry[0], entry[1]]);
+            let perms = u16::from_le_bytes([entry[2], entry[3]]);
+            let id = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+            let tag = match tag {
+                ACL_USER_OBJ => AclTag::UserObj,
+                ACL_USER => AclTag::User(id),
+                ACL_GROUP_OBJ => AclTag::GroupObj,
+                ACL_GROUP => AclTag::Group(id),
+                ACL_MASK => AclTag::Mask,
+                ACL_OTHER => AclTag::Other,
+                _ => return Err(Error::new(ErrorKind::InvalidData, "unknown acl tag")),
+            };
+            entries.push(AclEntry {
+                tag,
+                perms: (perms & 0o7) as u8,
+            });
+        }
+        Ok(Self::new(entries))
+    }
+
+    /// Convert to Linux `system.posix_acl_access`/`system.posix_acl_default` extended attribute
+    /// value.
+    ///
+    /// User and group names are mapped to local ids. Fails if any of the names is unknown.
+    pub fn to_xattr(&self) -> Result<Vec<u8>, Error> {
+        let mut value = Vec::with_capacity(4 + self.entries.len() * XATTR_ENTRY_LEN);
+        value.extend(XATTR_VERSION.to_le_bytes());
+        for entry in self.entries.iter() {
+            let (tag, id) = match &entry.tag {
+                AclTag::UserObj => (ACL_USER_OBJ, ACL_UNDEFINED_ID),
+                AclTag::User(id) => (ACL_USER, *id),
+                AclTag::UserName(name) => (ACL_USER, user_id(name)?.ok_or_else(|| unknown(name))?),
+                AclTag::GroupObj => (ACL_GROUP_OBJ, ACL_UNDEFINED_ID),
+                AclTag::Group(id) => (ACL_GROUP, *id),
+                AclTag::GroupName(name) => {
+                    (ACL_GROUP, group_id(name)?.ok_or_else(|| unknown(name))?)
+                }
+                AclTag::Mask => (ACL_MASK, ACL_UNDEFINED_ID),
+                AclTag::Other => (ACL_OTHER, ACL_UNDEFINED_ID),
+            };
+            value.extend(tag.to_le_bytes());
+            value.extend(u16::from(entry.perms & 0o7).to_le_bytes());
+            value.extend(id.to_le_bytes());
+        }
+        Ok(value)
+    }
+}
+
+impl FromStr for Acl {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = Vec::new();
+        for entry in s.split(['\n', ',']) {
+            // strip comments, e.g. effective rights
+            let entry = entry.split('#').next().unwrap_or_default().trim();
+            if entry.is_empty() {
+                continue;
+            }
+            entries.push(entry.parse()?);
+        }
+        Ok(Self::new(entries))
+    }
+}
+
+impl Display for Acl {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i != 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{entry}")?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Acl {
+    type Error = Error;
+
+    fn try_from(other: String) -> Result<Self, Self::Error> {
+        other.parse()
+    }
+}
+
+impl From<Acl> for String {
+    fn from(other: Acl) -> String {
+        other.to_string()
+    }
+}
+
+/// ACL entry.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AclEntry {
+    /// Whom the entry applies to.
+    pub tag: AclTag,
+    /// Permission bits: read (4), write (2) and execute (1).
+    pub perms: u8,
+}
+
+impl FromStr for AclEntry {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::new(ErrorKind::InvalidData, format!("invalid acl entry: {s}"));
+        let mut fields = s.split(':');
+        let (Some(tag), Some(qualifier), Some(perms), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid());
+        };
+        let qualifier = qualifier.trim();
+        let tag = match (tag.trim(), qualifier.is_empty()) {
+            ("user" | "u", true) => AclTag::UserObj,
+            ("user" | "u", false) => match qualifier.parse() {
+                Ok(id) => AclTag::User(id),
+                Err(_) => AclTag::UserName(qualifier.into()),
+            },
+            ("group" | "g", true) => AclTag::GroupObj,
+            ("group" | "g", false) => match qualifier.parse() {
+                Ok(id) => AclTag::Group(id),
+                Err(_) => AclTag::GroupName(qualifier.into()),
+            },
+            ("mask" | "m", true) => AclTag::Mask,
+            ("other" | "o", true) => AclTag::Other,
+            _ => return Err(invalid()),
+        };
+        let mut bits = 0;
+        for ch in perms.trim().chars() {
+            bits |= match ch {
+                'r' => 4,
+                'w' => 2,
+                'x' => 1,
+                '-' => 0,
+                _ => return Err(invalid()),
+            };
+        }
+        Ok(Self { tag, perms: bits })
+    }
+}
+
+impl Display for AclEntry {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match &self.tag {
+            AclTag::UserObj => f.write_str("user::")?,
+            AclTag::User(id) => write!(f, "user:{id}:")?,
+            AclTag::UserName(name) => write!(f, "user:{name}:")?,
+            AclTag::GroupObj => f.write_str("group::")?,
+            AclTag::Group(id) => write!(f, "group:{id}:")?,
+            AclTag::GroupName(name) => write!(f, "group:{name}:")?,
+            AclTag::Mask => f.write_str("mask::")?,
+            AclTag::Other => f.write_str("other::")?,
+        }
+        for (bit, ch) in [(4, 'r'), (2, 'w'), (1, 'x')] {
+            let ch = if self.perms & bit != 0 { ch } else { '-' };
+            write!(f, "{ch}")?;
+        }
+        Ok(())
+    }
+}
+
+/// ACL entry tag.
+///
+/// The order of the variants is the canonical order of the entries.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AclTag {
+    /// File owner.
+    UserObj,
+    /// User with the specified id.
+    User(u32),
+    /// User with the specified name.
+    UserName(String),
+    /// File group.
+    GroupObj,
+    /// Group with the specified id.
+    Group(u32),
+    /// Group with the specified name.
+    GroupName(String),
+    /// The maximum permissions granted by user, group and group object entries.
+    Mask,
+    /// Everyone else.
+    Other,
+}
+
+fn unknown(name: &str) -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        format!("unknown acl qualifier: {name}"),
+    )
+}
+
+const XATTR_VERSION: u32 = 2;
+const XATTR_ENTRY_LEN: usize = 8;
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_GROUP: u16 = 0x08;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+const ACL_UNDEFINED_ID: u32 = u32::MAX;
+
+#[cfg(test)]
+mod tests {
+    use arbitrary::Arbitrary;
+    use arbitrary::Unstructured;
+    use arbtest::arbtest;
+
+    use super::*;
+
+    #[test]
+    fn text_form() {
+        let acl: Acl = "user::rw-,user:1000:r--\ngroup::r-- # comment\nmask::rwx\no::-\ng:10:x"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            "user::rw-\nuser:1000:r--\ngroup::r--\ngroup:10:--x\nmask::rwx\nother::---",
+            acl.to_string()
+        );
+        let acl: Acl = "user::rw-,user:alice:rw-,group:staff:r--,group:10:r--"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            "user::rw-\nuser:alice:rw-\ngroup:10:r--\ngroup:staff:r--",
+            acl.to_string()
+        );
+        assert!("user:a:b:rw-".parse::<Acl>().is_err());
+        assert!("mask:1:rw-".parse::<Acl>().is_err());
+        assert!("user::rwz".parse::<Acl>().is_err());
+    }
+
+    #[test]
+    fn symmetry() {
+        arbtest(|u| {
+            let expected: Acl = u.arbitrary()?;
+            let actual: Acl = expected.to_string().parse().unwrap();
+            assert_eq!(expected, actual);
+            if expected.entries().iter().all(|entry| !entry.tag.is_named()) {
+                let actual = Acl::from_xattr(&expected.to_xattr().unwrap()).unwrap();
+                assert_eq!(expected, actual);
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn unknown_names() {
+        let acl: Acl = "user::rw-,user:no-such-user-zar:r--".parse().unwrap();
+        assert_eq!(ErrorKind::NotFound, acl.to_xattr().unwrap_err().kind());
+    }
+
+    impl AclTag {
+        fn is_named(&self) -> bool {
+            matches!(self, Self::UserName(..) | Self::GroupName(..))
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Acl {
+        fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+            let entries: Vec<AclEntry> = u.arbitrary()?;
+            Ok(Self::new(entries))
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for AclTag {
+        fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+            let name = |u: &mut Unstructured<'a>| -> arbitrary::Result<String> {
+                let len = u.int_in_range(0..=10)?;
+                let mut name = String::from(*u.choose(&["a", "b", "_"])?);
+                for _ in 0..len {
+                    name.push(*u.choose(&['a', 'z', '0', '9', '-', '_', '.'])?);
+                }
+                Ok(name)
+            };
+            Ok(match u.int_in_range(0..=7)? {
+                0 => Self::UserObj,
+                1 => Self::User(u.arbitrary()?),
+                2 => Self::UserName(name(u)?),
+                3 => Self::GroupObj,
+                4 => Self::Group(u.arbitrary()?),
+                5 => Self::GroupName(name(u)?),
+                6 => Self::Mask,
+                _ => Self::Other,
+            })
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for AclEntry {
+        fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+            Ok(Self {
+                tag: u.arbitrary()?,
+                perms: u.int_in_range(0..=7)?,
+            })
+        }
+    }
+}
@@ -1,25 +1,20 @@
 use std::collections::HashMap;
 use std::collections::VecDeque;
-use std::ffi::CStr;
-use std::fs::create_dir_all;
-use std::fs::set_permissions;
-use std::fs::File;
-use std::fs::Permissions;
+use std::fmt::Display;
+use std::fmt::Formatter;
 use std::io::Error;
 use std::io::ErrorKind;
 use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
 use std::io::Take;
-use std::os::unix::fs::lchown;
-use std::os::unix::fs::symlink;
-use std::os::unix::fs::PermissionsExt;
-use std::os::unix::net::UnixDatagram;
+use std::path::Component;
 use std::path::Path;
+use std::path::PathBuf;
 
 use base64ct::Base64;
 use base64ct::Encoding;
-use libc::makedev;
+use normalize_path::NormalizePath;
 use rsa::pkcs1v15::Signature as RsaSignature;
 use rsa::RsaPublicKey;
 use serde::Deserialize;
@@ -29,31 +24,46 @@ use x509_cert::der::Decode;
 use x509_cert::der::Encode;
 use x509_cert::Certificate;
 
-use crate::lchown as c_lchown;
-use crate::mkfifo;
-use crate::mknod;
-use crate::path_to_c_string;
-use crate::set_file_modified_time;
 use crate::xml;
 use crate::Checksum;
 use crate::ChecksumAlgo;
+use crate::ChecksumReader;
 use crate::Compression;
+use crate::Extractor;
+use crate::FileData;
 use crate::FileType;
 use crate::HardLink;
 use crate::Header;
+use crate::OwnerMap;
 use crate::RootCertVerifier;
 use crate::RsaVerifier;
 use crate::TrustAny;
 use crate::XarDecoder;
+use crate::XattrFilter;
 
 /// Archive reading and extraction options.
 #[derive(Clone, Debug)]
 pub struct ArchiveOptions {
-    preserve_mtime: bool,
-    preserve_owner: bool,
-    check_toc: bool,
-    check_files: bool,
-    verify: bool,
+    pub(crate) preserve_mtime: bool,
+    pub(crate) preserve_atime: bool,
+    pub(crate) preserve_owner: bool,
+    pub(crate) numeric_owner: bool,
+    pub(crate) owner_map: OwnerMap,
+    pub(crate) check_toc: bool,
+    pub(crate) check_files: bool,
+    pub(crate) check_extracted_files: bool,
+    pub(crate) verify: bool,
+    pub(crate) sanitize_paths: bool,
+    pub(crate) overwrite: Overwrite,
+    pub(crate) recursive_unlink: bool,
+    pub(crate) xattrs: bool,
+    pub(crate) xattr_filter: XattrFilter,
+    pub(crate) acls: bool,
+    pub(crate) file_flags: bool,
+    pub(crate) hard_links: HardLinkMode,
+    pub(crate) same_permissions: bool,
+    pub(crate) keep_setuid: bool,
+    pub(crate) umask: u32,
 }
 
 impl ArchiveOptions {
@@ -61,21 +71,46 @@ impl ArchiveOptions {
     pub fn new() -> Self {
         Self {
             preserve_mtime: false,
+            preserve_atime: false,
             preserve_owner: false,
+            numeric_owner: false,
+            owner_map: Default::default(),
             check_toc: true,
             check_files: true,
+            check_extracted_files: true,
             verify: false,
+            sanitize_paths: true,
+            overwrite: Default::default(),
+            recursive_unlink: false,
+            xattrs: true,
+            xattr_filter: Default::default(),
+            acls: false,
+            file_flags: false,
+            hard_links: Default::default(),
+            same_permissions: true,
+            keep_setuid: false,
+            umask: 0,
         }
     }
 
     /// Preserve file modification time.
     ///
+    /// Directory modification time is restored after all files have been extracted.
+    ///
     /// `false` by default.
     pub fn preserve_mtime(mut self, value: bool) -> Self {
         self.preserve_mtime = value;
         self
     }
 
+    /// Preserve file access time.
+    ///
+    /// `false` by default.
+    pub fn preserve_atime(mut self, value: bool) -> Self {
+        self.preserve_atime = value;
+        self
+    }
+
     /// Preserve file's user and group IDs.
     ///
     /// `false` by default.
@@ -84,6 +119,27 @@ impl ArchiveOptions {
         self
     }
 
+    /// Use archived user and group IDs even if the archive contains user and group names.
+    ///
+    /// By default the names are mapped to the IDs of the local users and groups with the same
+    /// names; the archived IDs are used only if there are no such users and groups.
+    ///
+    /// `false` by default.
+    pub fn numeric_owner(mut self, value: bool) -> Self {
+        self.numeric_owner = value;
+        self
+    }
+
+    /// Override and remap the archived owners before restoring them.
+    ///
+    /// Has no effect unless [`preserve_owner`](Self::preserve_owner) is enabled.
+    ///
+    /// The owners are not changed by default.
+    pub fn owner_map(mut self, map: OwnerMap) -> Self {
+        self.owner_map = map;
+        self
+    }
+
     /// Check table of contents hash.
     ///
     /// `true` by default.
@@ -100,6 +156,14 @@ impl ArchiveOptions {
         self
     }
 
+    /// Check the hashes of the files' decompressed contents.
+    ///
+    /// `true` by default.
+    pub fn check_extracted_files(mut self, value: bool) -> Self {
+        self.check_extracted_files = value;
+        self
+    }
+
     /// Verify archive's signature.
     ///
     /// `false` by default.
@@ -107,6 +171,122 @@ impl ArchiveOptions {
         self.verify = value;
         self
     }
+
+    /// Protect the file system outside of the destination directory on extraction.
+    ///
+    /// When enabled, leading `/` is stripped from file names, file names containing `..` are
+    /// rejected and symbolic links are never followed (including the ones that were extracted
+    /// earlier). All paths are resolved relative to the destination directory handle.
+    ///
+    /// `true` by default.
+    pub fn sanitize_paths(mut self, value: bool) -> Self {
+        self.sanitize_paths = value;
+        self
+    }
+
+    /// What to do with the files that already exist in the destination directory.
+    ///
+    /// [`Overwrite::Replace`] by default.
+    pub fn overwrite(mut self, value: Overwrite) -> Self {
+        self.overwrite = value;
+        self
+    }
+
+    /// Remove existing non-empty directories with their contents when they are replaced by other
+    /// file types (like tar's `--recursive-unlink`).
+    ///
+    /// If disabled, only empty directories are removed and replacing a non-empty directory fails.
+    ///
+    /// `false` by default.
+    pub fn recursive_unlink(mut self, value: bool) -> Self {
+        self.recursive_unlink = value;
+        self
+    }
+
+    /// Restore extended attributes on extraction.
+    ///
+    /// The attributes that require privileges (e.g. `trusted.*`, `security.*`) are skipped if
+    /// they can't be set.
+    ///
+    /// `true` by default.
+    pub fn xattrs(mut self, value: bool) -> Self {
+        self.xattrs = value;
+        self
+    }
+
+    /// Restore only the extended attributes selected by the filter.
+    ///
+    /// All attributes are selected by default.
+    pub fn xattr_filter(mut self, filter: XattrFilter) -> Self {
+        self.xattr_filter = filter;
+        self
+    }
+
+    /// Restore POSIX ACLs of regular files and directories on extraction.
+    ///
+    /// Only supported on Linux. The ACLs that can't be restored (e.g. on other platforms or
+    /// because of unknown user and group names) are skipped and reported as
+    /// [`warnings`](ExtractReport::warnings).
+    ///
+    /// `false` by default.
+    pub fn acls(mut self, value: bool) -> Self {
+        self.acls = value;
+        self
+    }
+
+    /// Restore file flags of regular files and directories on extraction.
+    ///
+    /// The flags are applied after all files have been extracted, because immutable and
+    /// append-only flags prevent further modifications.
+    ///
+    /// `false` by default.
+    pub fn file_flags(mut self, value: bool) -> Self {
+        self.file_flags = value;
+        self
+    }
+
+    /// How to extract hard links.
+    ///
+    /// [`HardLinkMode::Link`] by default.
+    pub fn hard_links(mut self, mode: HardLinkMode) -> Self {
+        self.hard_links = mode;
+        self
+    }
+
+    /// Restore archived file permissions.
+    ///
+    /// If disabled, the archived permissions are ignored and new files get `0o666` permissions
+    /// and new directories get `0o777` permissions (both are subject to
+    /// [`umask`](Self::umask)).
+    ///
+    /// `true` by default.
+    pub fn same_permissions(mut self, value: bool) -> Self {
+        self.same_permissions = value;
+        self
+    }
+
+    /// Keep setuid and setgid bits of the archived permissions.
+    ///
+    /// If disabled, these bits are cleared, except the setgid bit of directories that only makes
+    /// new files inherit the directory's group.
+    ///
+    /// `false` by default.
+    pub fn keep_setuid(mut self, value: bool) -> Self {
+        self.keep_setuid = value;
+        self
+    }
+
+    /// Clear the specified permission bits of every extracted file, e.g. `0o022`.
+    ///
+    /// Process umask doesn't affect the extracted files, because their permissions are set
+    /// explicitly after they have been created. Symbolic links are the exception: their
+    /// permissions can't be changed.
+    ///
+    /// `0` by default.
+    pub fn umask(mut self, value: u32) -> Self {
+        self.umask = value;
+        self
+    }
 }
 
 impl Default for ArchiveOptions {
@@ -115,17 +295,107 @@ impl Default for ArchiveOptions {
     }
 }
 
+/// Overwrite policy for the files that already exist in the destination directory.
+///
+/// Existing directories are never replaced by directories; their contents are merged with the
+/// contents of the archive instead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Overwrite {
+    /// Fail with [`AlreadyExists`](ErrorKind::AlreadyExists) error.
+    Error,
+    /// Keep the existing file.
+    Skip,
+    /// Keep the existing file if its modification time is newer than the archived one's,
+    /// otherwise replace it.
+    KeepNewer,
+    /// Remove the existing file before extracting the archived one.
+    ///
+    /// Symbolic links are removed rather than followed, even if they point to a directory.
+    /// Existing non-empty directories are replaced by other file types only if
+    /// [`recursive_unlink`](ArchiveOptions::recursive_unlink) is enabled.
+    #[default]
+    Replace,
+}
+
+/// How to extract hard links.
+///
+/// Hard links are created after all other files have been extracted. Copies are extracted from the
+/// archived original file and have the same contents, permissions and extended attributes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum HardLinkMode {
+    /// Create hard links.
+    #[default]
+    Link,
+    /// Copy the original file instead.
+    Copy,
+    /// Create hard links and copy the original file if the link can't be created because the
+    /// file system doesn't support hard links or the original is on a different file system
+    /// (`EPERM` and `EXDEV` errors).
+    LinkOrCopy,
+}
+
+/// The result of [`extract_best_effort`](ExtendedArchive::extract_best_effort).
+#[derive(Debug, Default)]
+pub struct ExtractReport {
+    /// The entries that were not extracted.
+    pub errors: Vec<EntryError>,
+    /// The entries that were extracted without some of their metadata, e.g. ACLs that are not
+    /// supported on this platform.
+    pub warnings: Vec<EntryError>,
+}
+
+impl ExtractReport {
+    /// Returns `true` if all selected entries were extracted.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// An error that occurred while extracting an archive entry.
+#[derive(Debug)]
+pub struct EntryError {
+    /// File path.
+    pub path: PathBuf,
+    /// File id.
+    pub id: u64,
+    /// The error.
+    pub error: Error,
+}
+
+impl EntryError {
+    /// Get the error kind.
+    pub fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+}
+
+impl Display for EntryError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+impl std::error::Error for EntryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
 /// XAR archive without any extra data.
 pub type Archive<R> = ExtendedArchive<R, ()>;
 
 /// XAR archive with extra data.
 pub struct ExtendedArchive<R: Read + Seek, X = ()> {
     files: Vec<xml::File<X>>,
+    // archive path -> file index
+    paths: HashMap<PathBuf, usize>,
+    // directory archive path -> child file indices
+    children: HashMap<PathBuf, Vec<usize>>,
+    // file id -> file index
+    ids: HashMap<u64, usize>,
     reader: R,
     heap_offset: u64,
-    preserve_mtime: bool,
-    preserve_owner: bool,
-    check_files: bool,
+    options: ArchiveOptions,
 }
 
 impl<R: Read + Seek, X: for<'a> Deserialize<'a> + Default> ExtendedArchive<R, X> {
@@ -244,19 +514,39 @@ impl<R: Read + Seek, X: for<'a> Deserialize<'a> + Default> ExtendedArchive<R, X>
             verifier.verify(&cert_data, &signature)?;
             root_cert_verifier.verify(&certificate)?;
         }
+        let files: Vec<_> = toc
+            .files
+            .into_iter()
+            .flat_map(xml::File::into_vec)
+            .collect();
+        let mut paths = HashMap::with_capacity(files.len());
+        let mut children: HashMap<_, Vec<_>> = HashMap::new();
+        let mut ids = HashMap::with_capacity(files.len());
+        for (i, file) in files.iter().enumerate() {
+            ids.entry(file.id).or_insert(i);
+            let path = normalize_archive_path(&file.name);
+            let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            children.entry(parent).or_default().push(i);
+            paths.entry(path).or_insert(i);
+        }
         Ok(Self {
-            files: toc.files,
+            files,
+            paths,
+            children,
+            ids,
             reader,
             heap_offset,
-            preserve_mtime: options.preserve_mtime,
-            preserve_owner: options.preserve_owner,
-            check_files: options.check_files,
+            options,
         })
     }
 }
 
 impl<R: Read + Seek, X> ExtendedArchive<R, X> {
     /// Get files.
+    ///
+    /// The file tree is flattened and file names are replaced with full archive paths.
+    /// Parent directories come before their descendants: each top-level entry is followed by
+    /// its descendants in breadth-first order (e.g. `a`, `a/b`, `a/d`, `a/b/c`).
     pub fn files(&self) -> &[xml::File<X>] {
         self.files.as_slice()
     }
@@ -271,158 +561,148 @@ impl<R: Read + Seek, X> ExtendedArchive<R, X> {
         Entry { i, archive: self }
     }
 
+    /// Find the index of the file with the specified archive path.
+    ///
+    /// Leading `/` and `.` path components are ignored.
+    pub fn find<P: AsRef<Path>>(&self, path: P) -> Option<usize> {
+        self.paths
+            .get(&normalize_archive_path(path.as_ref()))
+            .copied()
+    }
+
+    /// Get the file with the specified id.
+    pub(crate) fn entry_by_id(&mut self, id: u64) -> Option<Entry<'_, R, X>> {
+        let i = self.ids.get(&id).copied()?;
+        Some(self.entry(i))
+    }
+
+    /// Get the file with the specified archive path.
+    pub fn entry_by_path<P: AsRef<Path>>(&mut self, path: P) -> Option<Entry<'_, R, X>> {
+        let i = self.find(path)?;
+        Some(self.entry(i))
+    }
+
+    /// Get the indices of the direct children of the specified directory.
+    ///
+    /// Empty path denotes the root of the archive.
+    pub fn children<P: AsRef<Path>>(&self, path: P) -> &[usize] {
+        self.children
+            .get(&normalize_archive_path(path.as_ref()))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
     /// Extract the contents of the archive to `dest_dir`.
-    pub fn extract<P: AsRef<Path>>(mut self, dest_dir: P) -> Result<(), Error> {
-        use std::collections::hash_map::Entry::*;
-        let dest_dir = dest_dir.as_ref();
-        let mut dirs = Vec::new();
-        // id -> path
-        let mut file_paths = HashMap::new();
-        let mut hard_links = Vec::new();
-        // (dev, inode) -> id
-        let mut inodes = HashMap::new();
-        let preserve_mtime = self.preserve_mtime;
-        let self_preserve_owner = self.preserve_owner;
-        let c_preserve_mtime = |path: &CStr, file: &xml::File<X>| -> Result<(), Error> {
-            if preserve_mtime {
-                set_file_modified_time(path, file.mtime.0)?;
-            }
-            Ok(())
-        };
-        let preserve_owner = |path: &Path, file: &xml::File<X>| -> Result<(), Error> {
-            if self_preserve_owner {
-                lchown(path, Some(file.uid), Some(file.gid))?;
-            }
-            Ok(())
-        };
-        let c_preserve_owner = |path: &CStr, file: &xml::File<X>| -> Result<(), Error> {
-            if self_preserve_owner {
-                c_lchown(path, file.uid, file.gid)?;
-            }
+    pub fn extract<P: AsRef<Path>>(self, dest_dir: P) -> Result<(), Error> {
+        self.extract_filtered(dest_dir, |_| true)
+    }
+
+    /// Extract the files selected by `filter` to `dest_dir`.
+    ///
+    /// Missing parent directories of the selected files are created with `0o755` permissions.
+    /// Hard links whose original file is not selected are extracted as regular files.
+    ///
+    /// Use [`PathFilter`](crate::PathFilter) to select files by glob patterns or by paths:
+    ///
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use std::io::Error;
+    /// # fn main() -> Result<(), Error> {
+    /// let filter = zar::PathFilter::new().include("Distribution")?;
+    /// let archive = zar::Archive::new(File::open("archive.xar")?)?;
+    /// archive.extract_filtered("/tmp", |file| filter.matches(&file.name))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn extract_filtered<P, F>(self, dest_dir: P, filter: F) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&xml::File<X>) -> bool,
+    {
+        self.do_extract(dest_dir.as_ref(), filter, |e| Err(e.error))?;
+        Ok(())
+    }
+
+    /// Extract the files selected by `filter` to `dest_dir` skipping the entries that can't be
+    /// extracted.
+    ///
+    /// Unlike [`extract_filtered`](Self::extract_filtered) an error in one entry doesn't stop the
+    /// extraction. Instead the errors are collected in the returned report. The entries that
+    /// failed mid-way (e.g. because of a checksum mismatch) may be left partially extracted.
+    ///
+    /// Only the errors that affect the whole archive (e.g. failure to open the destination
+    /// directory) are returned as `Err`.
+    pub fn extract_best_effort<P, F>(self, dest_dir: P, filter: F) -> Result<ExtractReport, Error>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&xml::File<X>) -> bool,
+    {
+        let mut errors = Vec::new();
+        let warnings = self.do_extract(dest_dir.as_ref(), filter, |e| {
+            errors.push(e);
             Ok(())
-        };
+        })?;
+        Ok(ExtractReport { errors, warnings })
+    }
+
+    fn do_extract<F, E>(
+        mut self,
+        dest_dir: &Path,
+        mut filter: F,
+        mut on_error: E,
+    ) -> Result<Vec<EntryError>, Error>
+    where
+        F: FnMut(&xml::File<X>) -> bool,
+        E: FnMut(EntryError) -> Result<(), Error>,
+    {
+        let selected: Vec<bool> = self.files.iter().map(&mut filter).collect();
+        let mut extractor = Extractor::new(dest_dir, self.options.clone())?;
         for i in 0..self.num_entries() {
-            let mut entry = self.entry(i);
-            let dest_file = dest_dir.join(&entry.file().name);
-            let file_type: FileType = entry.file().kind;
-            file_paths.insert(entry.file().id, dest_file.clone());
-            match inodes.entry((entry.file().deviceno, entry.file().inode)) {
-                Vacant(v) => {
-                    if !matches!(file_type, FileType::HardLink(HardLink::Id(..))) {
-                        v.insert(entry.file().id);
-                    }
-                }
-                Occupied(o) => {
-                    let id = *o.get();
-                    // hard link
-                    hard_links.push((id, dest_file));
-                    continue;
-                }
+            if !selected[i] {
+                continue;
             }
-            match file_type {
-                FileType::File => {
-                    let mut file = File::create(&dest_file)?;
-                    if let Some(mut reader) = entry.reader()? {
-                        std::io::copy(&mut reader, &mut file)?;
-                    }
-                    if preserve_mtime {
-                        file.set_modified(entry.file().mtime.0)?;
-                    }
-                    drop(file);
-                    preserve_owner(&dest_file, entry.file())?;
-                    let perms = Permissions::from_mode(entry.file().mode.into());
-                    set_permissions(&dest_file, perms)?;
-                }
-                FileType::Directory => {
-                    create_dir_all(&dest_file)?;
-                    if preserve_mtime {
-                        File::open(&dest_file)?.set_modified(entry.file().mtime.0)?;
-                    }
-                    preserve_owner(&dest_file, entry.file())?;
-                    // apply proper permissions later when we have written all other files
-                    dirs.push((dest_file, entry.file().mode));
-                }
-                FileType::HardLink(hard_link) => match hard_link {
-                    HardLink::Original => {
-                        // ignore
-                    }
-                    HardLink::Id(id) => {
-                        // create hard links later because we might not have written
-                        // the original files by now
-                        hard_links.push((id, dest_file));
-                    }
-                },
-                FileType::Symlink => {
-                    let target = entry
-                        .file()
-                        .link()
-                        .ok_or(ErrorKind::InvalidData)?
-                        .target
-                        .as_path();
-                    symlink(target, &dest_file)?;
-                    let path = path_to_c_string(dest_file)?;
-                    c_preserve_mtime(&path, entry.file())?;
-                    c_preserve_owner(&path, entry.file())?;
-                }
-                FileType::Fifo => {
-                    let path = path_to_c_string(dest_file)?;
-                    let mode = entry.file().mode.into_inner();
-                    mkfifo(&path, mode as _)?;
-                    c_preserve_mtime(&path, entry.file())?;
-                    c_preserve_owner(&path, entry.file())?;
-                }
-                #[allow(unused_unsafe)]
-                FileType::CharacterSpecial | FileType::BlockSpecial => {
-                    let path = path_to_c_string(dest_file)?;
-                    let dev = entry.file().device().ok_or(ErrorKind::InvalidData)?;
-                    let dev = unsafe { makedev(dev.major as _, dev.minor as _) };
-                    let mode = entry.file().mode.into_inner();
-                    mknod(&path, mode as _, dev as _)?;
-                    c_preserve_mtime(&path, entry.file())?;
-                    c_preserve_owner(&path, entry.file())?;
-                }
-                FileType::Socket => {
-                    UnixDatagram::bind(&dest_file)?;
-                    let path = path_to_c_string(dest_file)?;
-                    c_preserve_mtime(&path, entry.file())?;
-                    c_preserve_owner(&path, entry.file())?;
-                }
+            if let Err(error) = self.extract_entry(&mut extractor, i, &selected) {
+                let file = &self.files[i];
+                on_error(EntryError {
+                    path: file.name.clone(),
+                    id: file.id,
+                    error,
+                })?;
             }
         }
-        for (id, dest_file) in hard_links.into_iter() {
-            let original = file_paths.get(&id).ok_or(ErrorKind::InvalidData)?;
-            std::fs::hard_link(original, &dest_file)?;
-        }
-        dirs.sort_unstable_by(|a, b| b.0.cmp(&a.0));
-        for (path, mode) in dirs.into_iter() {
-            let perms = Permissions::from_mode(mode.into());
-            set_permissions(&path, perms)?;
-        }
-        Ok(())
+        extractor.finish(&mut self, on_error)
     }
-}
 
-#[inline]
-fn seek_to_file<R: Read + Seek>(
-    reader: &mut R,
-    offset: u64,
-    length: u64,
-    archived_checksum: &Checksum,
-    check_files: bool,
-) -> Result<(), Error> {
-    let mut file_bytes = vec![0_u8; length as usize];
-    reader.seek(SeekFrom::Start(offset))?;
-    reader.read_exact(&mut file_bytes[..])?;
-    if check_files {
-        let actual_checksum = archived_checksum.algo().hash(&file_bytes[..]);
-        if archived_checksum != &actual_checksum {
-            return Err(Error::other("file checksum mismatch"));
+    fn extract_entry(
+        &mut self,
+        extractor: &mut Extractor,
+        i: usize,
+        selected: &[bool],
+    ) -> Result<(), Error> {
+        if let FileType::HardLink(HardLink::Id(id)) = self.files[i].kind {
+            if let Some(j) = self.ids.get(&id).copied() {
+                if !selected[j] && !extractor.contains(id) {
+                    let name = self.files[i].name.clone();
+                    return extractor.extract_hard_link_original(&mut self.entry(j), &name);
+                }
+            }
         }
+        extractor.extract(&mut self.entry(i))
     }
-    reader.seek(SeekFrom::Start(offset))?;
-    Ok(())
 }
 
+fn normalize_archive_path(path: &Path) -> PathBuf {
+    path.normalize()
+        .components()
+        .filter(|c| matches!(c, Component::Normal(..) | Component::ParentDir))
+        .collect()
+}
+
+/// File contents reader.
+///
+/// Decompresses the contents and verifies both archived and extracted checksums.
+pub type EntryReader<'a, R> = ChecksumReader<XarDecoder<ChecksumReader<Take<&'a mut R>>>>;
+
 /// File entry that is currently being read.
 pub struct Entry<'a, R: Read + Seek, X> {
     archive: &'a mut ExtendedArchive<R, X>,
@@ -435,34 +715,32 @@ impl<R: Read + Seek, X> Entry<'_, R, X> {
     /// The reader is provided for every regular file.
     /// If the file is empty, the stream will not contain any bytes.
     /// For non-regular-file entries `Ok(None)` is returned.
-    pub fn reader(&mut self) -> Result<Option<XarDecoder<Take<&mut R>>>, Error> {
+    ///
+    /// If [`check_files`](ArchiveOptions::check_files) is enabled, the checksum of the archived
+    /// contents is computed while the contents are being read. The same applies to the
+    /// decompressed contents and [`check_extracted_files`](ArchiveOptions::check_extracted_files).
+    /// A mismatch is reported as [`ChecksumMismatch`](crate::ChecksumMismatch) error at the end of
+    /// the stream.
+    pub fn reader(&mut self) -> Result<Option<EntryReader<'_, R>>, Error> {
         let file = &self.archive.files[self.i];
         match file.data() {
-            Some(data) => {
-                debug_assert!(data.archived_checksum.algo == data.archived_checksum.value.algo());
-                let compression: Compression = data.encoding.style.as_str().into();
-                let length = data.length;
-                seek_to_file(
-                    self.archive.reader.by_ref(),
-                    self.archive.heap_offset + data.offset,
-                    data.length,
-                    &data.archived_checksum.value,
-                    self.archive.check_files,
-                )?;
-                // we need decoder based on compression, otherwise we can accidentally decompress the
-                // file with octet-stream compression
-                Ok(Some(
-                    compression.decoder(self.archive.reader.by_ref().take(length)),
-                ))
-            }
+            Some(data) => Ok(Some(data_reader(
+                &mut self.archive.reader,
+                self.archive.heap_offset,
+                &self.archive.options,
+                data,
+            )?)),
             None if file.kind == FileType::File
                 || file.kind == FileType::HardLink(HardLink::Original) =>
             {
                 // The `FileData` may not be stored for empty files.
                 let compression = Compression::None;
-                Ok(Some(
-                    compression.decoder(self.archive.reader.by_ref().take(0)),
-                ))
+                let reader = ChecksumReader::new(self.archive.reader.by_ref().take(0), None, 0);
+                Ok(Some(ChecksumReader::new(
+                    compression.decoder(reader),
+                    None,
+                    0,
+                )))
             }
             // Not a regular file.
             None => Ok(None),
@@ -473,6 +751,76 @@ impl<R: Read + Seek, X> Entry<'_, R, X> {
     pub fn file(&self) -> &xml::File<X> {
         &self.archive.files[self.i]
     }
+
+    /// Read the value of the extended attribute with the specified name.
+    ///
+    /// The attributes are listed by [`xattrs`](xml::File::xattrs).
+    /// Returns `Ok(None)` if the file has no such attribute.
+    /// The checksums are verified the same way as in [`reader`](Self::reader).
+    pub fn read_xattr(&mut self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        let file = &self.archive.files[self.i];
+        let Some(xattr) = file.xattrs().iter().find(|xattr| xattr.name == name) else {
+            return Ok(None);
+        };
+        let mut reader = data_reader(
+            &mut self.archive.reader,
+            self.archive.heap_offset,
+            &self.archive.options,
+            &xattr.data(),
+        )?;
+        let mut value = Vec::new();
+        reader.read_to_end(&mut value)?;
+        Ok(Some(value))
+    }
+
+    /// Extract the entry to the specified path.
+    ///
+    /// The metadata is restored the same way as in [`extract`](ExtendedArchive::extract).
+    /// Hard links are extracted as regular files with the contents of the original file.
+    /// The parent directory of `path` must exist.
+    pub fn extract_to<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let name = Path::new(path.file_name().ok_or(ErrorKind::InvalidInput)?);
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut extractor = Extractor::new(dir, self.archive.options.clone())?;
+        match self.file().kind {
+            FileType::HardLink(HardLink::Id(id)) => {
+                let i = *self.archive.ids.get(&id).ok_or(ErrorKind::InvalidData)?;
+                extractor.extract_as(&mut self.archive.entry(i), name)?;
+            }
+            _ => extractor.extract_as(self, name)?,
+        }
+        extractor.finish(self.archive, |e| Err(e.error))?;
+        Ok(())
+    }
+}
+
+fn data_reader<'a, R: Read + Seek>(
+    reader: &'a mut R,
+    heap_offset: u64,
+    options: &ArchiveOptions,
+    data: &FileData,
+) -> Result<EntryReader<'a, R>, Error> {
+    debug_assert!(data.archived_checksum.algo == data.archived_checksum.value.algo());
+    let compression: Compression = data.encoding.style.as_str().into();
+    reader.seek(SeekFrom::Start(heap_offset + data.offset))?;
+    let expected = options
+        .check_files
+        .then(|| data.archived_checksum.value.clone());
+    let reader = ChecksumReader::new(reader.take(data.length), expected, data.length);
+    let expected = options
+        .check_extracted_files
+        .then(|| data.extracted_checksum.value.clone());
+    // we need decoder based on compression, otherwise we can accidentally decompress the
+    // file with octet-stream compression
+    Ok(ChecksumReader::new(
+        compression.decoder(reader),
+        expected,
+        data.size,
+    ))
 }
 
 const RSA_SHA1_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.5");
@@ -480,9 +828,14 @@ const RSA_SHA256_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.1
 
 #[cfg(test)]
 mod tests {
+    use std::fs::create_dir_all;
     use std::fs::File;
+    use std::io::Cursor;
+    use std::io::Write;
+    use std::os::unix::fs::FileTypeExt;
     use std::sync::Once;
     use std::time::Duration;
+    use std::time::SystemTime;
 
     use arbtest::arbtest;
     use random_dir::DirBuilder;
@@ -495,10 +848,15 @@ mod tests {
     use x509_cert::spki::EncodePublicKey;
 
     use super::*;
+    use crate::no_extra_contents;
+    use crate::path_to_c_string;
     use crate::BuilderOptions;
+    use crate::FileMetadata;
     use crate::NoSigner;
+    use crate::PathFilter;
     use crate::RsaSigner;
     use crate::Signer;
+    use crate::UnsignedBuilder;
 
     #[test]
     fn xar_unsigned_write_read() {
@@ -544,6 +902,729 @@ mod tests {
         test_xar_write_read(signer, verifier, true, checksum_algo);
     }
 
+    #[test]
+    fn xar_nested_read_extract() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        create_dir_all(src_dir.join("a").join("b")).unwrap();
+        File::create(src_dir.join("a").join("c")).unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut xar =
+            BuilderOptions::new().create(File::create(&xar_path).unwrap(), None::<NoSigner>);
+        xar.append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        xar.finish().unwrap();
+        let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        let mut names: Vec<_> = archive
+            .files()
+            .iter()
+            .map(|file| file.name.clone())
+            .collect();
+        names.sort();
+        assert_eq!(
+            vec![Path::new("a"), Path::new("a/b"), Path::new("a/c")],
+            names
+        );
+        assert_eq!(3, archive.num_entries());
+        let dest_dir = workdir.path().join("dest");
+        create_dir_all(&dest_dir).unwrap();
+        archive.extract(&dest_dir).unwrap();
+        assert!(dest_dir.join("a").join("b").is_dir());
+        assert!(dest_dir.join("a").join("c").is_file());
+    }
+
+    #[test]
+    fn xar_deep_write_read() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        let mut dir = src_dir.clone();
+        let mut expected_contents = Vec::new();
+        for i in 0..12 {
+            dir.push(format!("dir{}", i));
+            create_dir_all(&dir).unwrap();
+            let path = dir.join("file");
+            let contents = format!("contents {}", i).repeat(i + 1);
+            std::fs::write(&path, &contents).unwrap();
+            let archive_path = path.strip_prefix(&src_dir).unwrap().to_path_buf();
+            expected_contents.push((archive_path, contents.into_bytes()));
+        }
+        let xar_path = workdir.path().join("test.xar");
+        let mut xar =
+            BuilderOptions::new().create(File::create(&xar_path).unwrap(), None::<NoSigner>);
+        xar.append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        assert_eq!(1, xar.files().len());
+        xar.finish().unwrap();
+        let mut archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        assert_eq!(2 * expected_contents.len(), archive.num_entries());
+        let mut ids: Vec<_> = archive.files().iter().map(|file| file.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(archive.num_entries(), ids.len());
+        let mut actual_contents = Vec::new();
+        for i in 0..archive.num_entries() {
+            let mut entry = archive.entry(i);
+            if entry.file().kind == FileType::Directory {
+                continue;
+            }
+            let path = entry.file().name.clone();
+            let mut buf = Vec::new();
+            entry
+                .reader()
+                .unwrap()
+                .unwrap()
+                .read_to_end(&mut buf)
+                .unwrap();
+            actual_contents.push((path, buf));
+        }
+        actual_contents.sort();
+        expected_contents.sort();
+        assert_eq!(expected_contents, actual_contents);
+        let dest_dir = workdir.path().join("dest");
+        create_dir_all(&dest_dir).unwrap();
+        archive.extract(&dest_dir).unwrap();
+        for (path, contents) in expected_contents.iter() {
+            assert_eq!(contents, &std::fs::read(dest_dir.join(path)).unwrap());
+        }
+    }
+
+    #[test]
+    fn xar_corrupted_file() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        create_dir_all(&src_dir).unwrap();
+        std::fs::write(src_dir.join("file"), "contents").unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut xar =
+            BuilderOptions::new().create(File::create(&xar_path).unwrap(), None::<NoSigner>);
+        xar.append_dir_all(&src_dir, Compression::None, crate::no_extra_contents)
+            .unwrap();
+        xar.finish().unwrap();
+        // the file contents are stored at the end of the archive
+        let mut bytes = std::fs::read(&xar_path).unwrap();
+        if let Some(last) = bytes.last_mut() {
+            *last = b'!';
+        }
+        std::fs::write(&xar_path, &bytes).unwrap();
+        let mut archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        let mut entry = archive.entry(0);
+        let mut reader = entry.reader().unwrap().unwrap();
+        let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(error
+            .into_inner()
+            .unwrap()
+            .downcast::<crate::ChecksumMismatch>()
+            .is_ok());
+        // archived checksum is not verified, tampered contents are detected via extracted checksum
+        let mut archive = Archive::with_options(
+            File::open(&xar_path).unwrap(),
+            ArchiveOptions::new().check_files(false),
+        )
+        .unwrap();
+        let mut entry = archive.entry(0);
+        let mut reader = entry.reader().unwrap().unwrap();
+        let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(error
+            .into_inner()
+            .unwrap()
+            .downcast::<crate::ChecksumMismatch>()
+            .is_ok());
+        let dest_dir = workdir.path().join("dest");
+        create_dir_all(&dest_dir).unwrap();
+        let archive = Archive::with_options(
+            File::open(&xar_path).unwrap(),
+            ArchiveOptions::new().check_files(false),
+        )
+        .unwrap();
+        assert!(archive.extract(&dest_dir).is_err());
+        let mut archive = Archive::with_options(
+            File::open(&xar_path).unwrap(),
+            ArchiveOptions::new()
+                .check_files(false)
+                .check_extracted_files(false),
+        )
+        .unwrap();
+        let mut buf = Vec::new();
+        let mut entry = archive.entry(0);
+        let mut reader = entry.reader().unwrap().unwrap();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(b"content!".as_slice(), &buf[..]);
+    }
+
+    #[test]
+    fn data_reader_length_mismatch() {
+        let contents = b"contents";
+        let mut compressed = Vec::new();
+        let mut encoder = Compression::Gzip.encoder(&mut compressed).unwrap();
+        encoder.write_all(contents).unwrap();
+        encoder.finish().unwrap();
+        let algo = ChecksumAlgo::Sha256;
+        let data = |heap: &[u8], size: u64| FileData {
+            archived_checksum: algo.hash(heap).into(),
+            extracted_checksum: algo.hash(contents).into(),
+            encoding: Compression::Gzip.into(),
+            offset: 0,
+            size,
+            length: heap.len() as u64,
+            holes: Vec::new(),
+        };
+        let read = |heap: &[u8], data: &FileData| -> Result<Vec<u8>, Error> {
+            let mut cursor = Cursor::new(heap);
+            let mut reader = data_reader(&mut cursor, 0, &ArchiveOptions::new(), data)?;
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf)?;
+            Ok(buf)
+        };
+        assert_eq!(
+            contents.as_slice(),
+            read(&compressed, &data(&compressed, 8)).unwrap()
+        );
+        // the decoder stops before the declared size
+        let error = read(&compressed, &data(&compressed, 9)).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, error.kind());
+        // the decoder produces more than the declared size
+        let error = read(&compressed, &data(&compressed, 7)).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, error.kind());
+        // tampered data after the end of the compressed stream
+        let mut heap = compressed.clone();
+        heap.extend(b"trailing");
+        let valid = data(&heap, 8);
+        if let Some(last) = heap.last_mut() {
+            *last = b'!';
+        }
+        let error = read(&heap, &valid).unwrap_err();
+        assert!(error
+            .into_inner()
+            .unwrap()
+            .downcast::<crate::ChecksumMismatch>()
+            .is_ok());
+    }
+
+    #[test]
+    fn xar_append_dir_all_with() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        create_dir_all(src_dir.join("a")).unwrap();
+        create_dir_all(src_dir.join("skip").join("b")).unwrap();
+        std::fs::write(src_dir.join("a").join("file"), "contents").unwrap();
+        std::fs::write(src_dir.join("skip").join("b").join("file"), "contents").unwrap();
+        let fifo = path_to_c_string(src_dir.join("a").join("fifo")).unwrap();
+        assert_eq!(0, unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) });
+        let xar_path = workdir.path().join("test.xar");
+        let mut xar = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        let mut source_paths = Vec::new();
+        xar.append_dir_all_with(&src_dir, Compression::Gzip, |file, archive_path, path| {
+            source_paths.push(path.strip_prefix(&src_dir).unwrap().to_path_buf());
+            if archive_path == Path::new("skip") || file.kind == FileType::Fifo {
+                return Ok(false);
+            }
+            if archive_path == Path::new("a/file") {
+                file.name = "renamed".into();
+            }
+            file.mode = 0o600.into();
+            file.mtime = Default::default();
+            Ok(true)
+        })
+        .unwrap();
+        xar.finish().unwrap();
+        source_paths.sort();
+        assert_eq!(
+            vec![
+                Path::new("a"),
+                Path::new("a/fifo"),
+                Path::new("a/file"),
+                Path::new("skip")
+            ],
+            source_paths
+        );
+        let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        let names: Vec<_> = archive
+            .files()
+            .iter()
+            .map(|file| file.name.clone())
+            .collect();
+        assert_eq!(vec![PathBuf::from("a"), PathBuf::from("a/renamed")], names);
+        for file in archive.files() {
+            assert_eq!(0o600, file.mode.into_inner());
+            assert_eq!(SystemTime::UNIX_EPOCH, file.mtime.0);
+        }
+        let dest_dir = workdir.path().join("dest");
+        create_dir_all(&dest_dir).unwrap();
+        archive.extract(&dest_dir).unwrap();
+        assert_eq!(
+            "contents",
+            std::fs::read_to_string(dest_dir.join("a").join("renamed")).unwrap()
+        );
+    }
+
+    #[test]
+    fn xar_append_dir_all_with_skip_before_read() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        create_dir_all(&src_dir).unwrap();
+        std::fs::write(src_dir.join("a"), "a").unwrap();
+        std::fs::write(src_dir.join("b"), "b").unwrap();
+        let mut xar = UnsignedBuilder::new_unsigned(Vec::new());
+        xar.append_dir_all_with(&src_dir, Compression::Gzip, |file, _archive_path, _path| {
+            // The contents are not read yet.
+            assert!(file.data.is_none());
+            Ok(true)
+        })
+        .unwrap();
+        // Names with slashes are rejected.
+        let mut xar = UnsignedBuilder::new_unsigned(Vec::new());
+        let error = xar
+            .append_dir_all_with(&src_dir, Compression::Gzip, |file, archive_path, _path| {
+                if archive_path == Path::new("a") {
+                    file.name = "x/y".into();
+                }
+                Ok(true)
+            })
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, error.kind());
+        // Renaming to an existing sibling is rejected regardless of the walk order.
+        for renamed in ["a", "b"] {
+            let other = if renamed == "a" { "b" } else { "a" };
+            let mut xar = UnsignedBuilder::new_unsigned(Vec::new());
+            let error = xar
+                .append_dir_all_with(&src_dir, Compression::Gzip, |file, archive_path, _path| {
+                    if archive_path == Path::new(renamed) {
+                        file.name = other.into();
+                    }
+                    Ok(true)
+                })
+                .unwrap_err();
+            assert_eq!(ErrorKind::AlreadyExists, error.kind());
+        }
+    }
+
+    #[test]
+    fn xar_heap_file() {
+        let workdir = TempDir::new().unwrap();
+        arbtest(|u| {
+            let directory = DirBuilder::new().printable_names(true).create(u)?;
+            let xar_path = workdir.path().join("test.xar");
+            let mut xar = BuilderOptions::new().create_with_heap::<_, _, (), _>(
+                File::create(&xar_path).unwrap(),
+                None::<NoSigner>,
+                tempfile::tempfile().unwrap(),
+            );
+            // skip every other file to make sure that their contents are not archived
+            let mut i = 0;
+            xar.append_dir_all_with(directory.path(), Compression::Gzip, |file, _, _| {
+                i += 1;
+                Ok(file.kind == FileType::Directory || i % 2 == 0)
+            })
+            .unwrap();
+            xar.finish().unwrap();
+            let dest_dir = workdir.path().join("dest");
+            let _ = std::fs::remove_dir_all(&dest_dir);
+            create_dir_all(&dest_dir).unwrap();
+            let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+            let names: Vec<_> = archive
+                .files()
+                .iter()
+                .filter(|file| file.kind == FileType::File)
+                .map(|file| file.name.clone())
+                .collect();
+            archive.extract(&dest_dir).unwrap();
+            for name in names.iter() {
+                assert_eq!(
+                    std::fs::read(directory.path().join(name)).unwrap(),
+                    std::fs::read(dest_dir.join(name)).unwrap()
+                );
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn xar_append_entries() {
+        let workdir = TempDir::new().unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut xar = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        let metadata = FileMetadata {
+            mode: 0o640.into(),
+            uid: 1000,
+            gid: 1000,
+            mtime: xml::Timestamp(SystemTime::UNIX_EPOCH + Duration::from_secs(1)),
+            ..Default::default()
+        };
+        xar.append_file("a/b/file", &metadata, Compression::Gzip, &b"contents"[..])
+            .unwrap();
+        xar.append_file("a/empty", &metadata, Compression::Gzip, std::io::empty())
+            .unwrap();
+        xar.append_file("/c", &metadata, Compression::Xz, "other".as_bytes())
+            .unwrap();
+        let dir_metadata = FileMetadata {
+            mode: 0o700.into(),
+            ..metadata.clone()
+        };
+        xar.append_dir("a", &dir_metadata).unwrap();
+        xar.append_symlink("a/link", &metadata, "b/file").unwrap();
+        xar.append_special("fifo", &metadata, FileType::Fifo, None)
+            .unwrap();
+        assert_eq!(
+            ErrorKind::AlreadyExists,
+            xar.append_file("c", &metadata, Compression::Gzip, &b""[..])
+                .unwrap_err()
+                .kind()
+        );
+        assert_eq!(
+            ErrorKind::InvalidInput,
+            xar.append_dir("a/../d", &metadata).unwrap_err().kind()
+        );
+        assert_eq!(
+            ErrorKind::InvalidInput,
+            xar.append_special("dev", &metadata, FileType::CharacterSpecial, None)
+                .unwrap_err()
+                .kind()
+        );
+        xar.finish().unwrap();
+        let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        let files: Vec<_> = archive
+            .files()
+            .iter()
+            .map(|file| (file.name.clone(), file.kind, file.mode.into_inner()))
+            .collect();
+        assert_eq!(
+            vec![
+                (PathBuf::from("a"), FileType::Directory, 0o700),
+                (PathBuf::from("a/b"), FileType::Directory, 0o755),
+                (PathBuf::from("a/empty"), FileType::File, 0o640),
+                (PathBuf::from("a/link"), FileType::Symlink, 0o640),
+                (PathBuf::from("a/b/file"), FileType::File, 0o640),
+                (PathBuf::from("c"), FileType::File, 0o640),
+                (PathBuf::from("fifo"), FileType::Fifo, 0o640),
+            ],
+            files
+        );
+        let dest_dir = workdir.path().join("dest");
+        create_dir_all(&dest_dir).unwrap();
+        archive.extract(&dest_dir).unwrap();
+        assert_eq!(
+            "contents",
+            std::fs::read_to_string(dest_dir.join("a/link")).unwrap()
+        );
+        assert_eq!(
+            "",
+            std::fs::read_to_string(dest_dir.join("a/empty")).unwrap()
+        );
+        assert_eq!(
+            "other",
+            std::fs::read_to_string(dest_dir.join("c")).unwrap()
+        );
+        assert!(dest_dir
+            .join("fifo")
+            .metadata()
+            .unwrap()
+            .file_type()
+            .is_fifo());
+    }
+
+    #[test]
+    fn xar_append_path_with_name() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        create_dir_all(src_dir.join("Foo.app").join("Contents")).unwrap();
+        std::fs::write(
+            src_dir.join("Foo.app").join("Contents").join("Info.plist"),
+            "plist",
+        )
+        .unwrap();
+        std::fs::write(src_dir.join("postinstall"), "script").unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut xar = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        xar.append_path_with_name(
+            src_dir.join("postinstall"),
+            "Scripts/postinstall",
+            Compression::Gzip,
+        )
+        .unwrap();
+        xar.append_path_with_name(
+            src_dir.join("Foo.app"),
+            "/Payload/Applications/Foo.app",
+            Compression::Gzip,
+        )
+        .unwrap();
+        assert_eq!(
+            ErrorKind::AlreadyExists,
+            xar.append_path_with_name(
+                src_dir.join("postinstall"),
+                "Scripts/postinstall",
+                Compression::Gzip,
+            )
+            .unwrap_err()
+            .kind()
+        );
+        xar.finish().unwrap();
+        let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        let mut names: Vec<_> = archive
+            .files()
+            .iter()
+            .map(|file| file.name.clone())
+            .collect();
+        names.sort();
+        assert_eq!(
+            vec![
+                PathBuf::from("Payload"),
+                PathBuf::from("Payload/Applications"),
+                PathBuf::from("Payload/Applications/Foo.app"),
+                PathBuf::from("Payload/Applications/Foo.app/Contents"),
+                PathBuf::from("Payload/Applications/Foo.app/Contents/Info.plist"),
+                PathBuf::from("Scripts"),
+                PathBuf::from("Scripts/postinstall"),
+            ],
+            names
+        );
+        let dest_dir = workdir.path().join("dest");
+        create_dir_all(&dest_dir).unwrap();
+        archive.extract(&dest_dir).unwrap();
+        assert_eq!(
+            "script",
+            std::fs::read_to_string(dest_dir.join("Scripts/postinstall")).unwrap()
+        );
+        assert_eq!(
+            "plist",
+            std::fs::read_to_string(
+                dest_dir.join("Payload/Applications/Foo.app/Contents/Info.plist")
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn xar_path_filter() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        create_dir_all(src_dir.join(".git")).unwrap();
+        create_dir_all(src_dir.join("a").join("b")).unwrap();
+        std::fs::write(src_dir.join(".git").join("config"), "").unwrap();
+        std::fs::write(src_dir.join("a").join("x.o"), "").unwrap();
+        std::fs::write(src_dir.join("a").join("b").join("x.c"), "").unwrap();
+        std::fs::write(src_dir.join("c"), "").unwrap();
+        let archive_names = |filter: PathFilter| {
+            let xar_path = workdir.path().join("test.xar");
+            let mut xar = BuilderOptions::new()
+                .path_filter(filter)
+                .create(File::create(&xar_path).unwrap(), None::<NoSigner>);
+            xar.append_dir_all(&src_dir, Compression::Gzip, no_extra_contents)
+                .unwrap();
+            xar.finish().unwrap();
+            let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+            let mut names: Vec<_> = archive
+                .files()
+                .iter()
+                .map(|file| file.name.to_str().unwrap().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+        let filter = PathFilter::new()
+            .exclude(".git")
+            .unwrap()
+            .exclude("*.o")
+            .unwrap();
+        assert_eq!(vec!["a", "a/b", "a/b/x.c", "c"], archive_names(filter));
+        let filter = PathFilter::new().include("a/b/*.c").unwrap();
+        assert_eq!(vec!["a", "a/b", "a/b/x.c"], archive_names(filter));
+    }
+
+    #[test]
+    fn xar_follow_symlinks() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        create_dir_all(src_dir.join("a")).unwrap();
+        std::fs::write(src_dir.join("a").join("file"), "contents").unwrap();
+        std::os::unix::fs::symlink("a", src_dir.join("link")).unwrap();
+        std::os::unix::fs::symlink("..", src_dir.join("a").join("loop")).unwrap();
+        std::os::unix::fs::symlink("missing", src_dir.join("broken")).unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut xar = BuilderOptions::new()
+            .follow_symlinks(true)
+            .create(File::create(&xar_path).unwrap(), None::<NoSigner>);
+        xar.append_dir_all(&src_dir, Compression::Gzip, no_extra_contents)
+            .unwrap();
+        xar.finish().unwrap();
+        let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        let mut files: Vec<_> = archive
+            .files()
+            .iter()
+            .map(|file| (file.name.to_str().unwrap().to_string(), file.kind))
+            .collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        let files: Vec<_> = files
+            .into_iter()
+            .map(|(name, kind)| {
+                let kind = match kind {
+                    FileType::HardLink(..) => FileType::File,
+                    other => other,
+                };
+                (name, kind)
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("a".to_string(), FileType::Directory),
+                ("a/file".to_string(), FileType::File),
+                ("a/loop".to_string(), FileType::Directory),
+                ("broken".to_string(), FileType::Symlink),
+                ("link".to_string(), FileType::Directory),
+                ("link/file".to_string(), FileType::File),
+                ("link/loop".to_string(), FileType::Directory),
+            ],
+            files
+        );
+        let dest_dir = workdir.path().join("dest");
+        create_dir_all(&dest_dir).unwrap();
+        archive.extract(&dest_dir).unwrap();
+        assert_eq!(
+            "contents",
+            std::fs::read_to_string(dest_dir.join("link").join("file")).unwrap()
+        );
+        assert!(dest_dir.join("link").symlink_metadata().unwrap().is_dir());
+    }
+
+    #[test]
+    fn xar_reproducible() {
+        let workdir = TempDir::new().unwrap();
+        let build = |names: &[&str]| {
+            let src_dir = TempDir::new_in(workdir.path()).unwrap();
+            for name in names.iter() {
+                let path = src_dir.path().join(name);
+                create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, name).unwrap();
+            }
+            std::fs::hard_link(src_dir.path().join("b"), src_dir.path().join("link")).unwrap();
+            let mut xar = BuilderOptions::new()
+                .reproducible(true)
+                .source_date_epoch(Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1000)))
+                .create(Vec::new(), None::<NoSigner>);
+            xar.append_dir_all(src_dir.path(), Compression::Gzip, no_extra_contents)
+                .unwrap();
+            xar.finish().unwrap()
+        };
+        let expected = build(&["a/x", "a/y", "b", "c/z"]);
+        let actual = build(&["c/z", "b", "a/y", "a/x"]);
+        assert!(expected == actual, "the archives differ");
+        let archive = Archive::new(Cursor::new(actual)).unwrap();
+        for file in archive.files() {
+            assert_eq!(0, file.deviceno);
+            assert_eq!(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(1000),
+                file.mtime.0
+            );
+            assert_eq!(file.mtime, file.atime);
+        }
+    }
+
+    #[test]
+    fn xar_find() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        create_dir_all(src_dir.join("a").join("b")).unwrap();
+        std::fs::write(src_dir.join("a").join("b").join("c"), "contents").unwrap();
+        File::create(src_dir.join("a").join("d")).unwrap();
+        File::create(src_dir.join("e")).unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut xar =
+            BuilderOptions::new().create(File::create(&xar_path).unwrap(), None::<NoSigner>);
+        xar.append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        xar.finish().unwrap();
+        let mut archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        for path in ["a", "a/b", "a/b/c", "a/d", "e"] {
+            let i = archive.find(path).unwrap();
+            assert_eq!(Path::new(path), archive.files()[i].name);
+        }
+        assert_eq!(archive.find("a/b/c"), archive.find("/a/./b/c"));
+        assert_eq!(None, archive.find("a/c"));
+        let mut entry = archive.entry_by_path("a/b/c").unwrap();
+        let mut buf = Vec::new();
+        entry
+            .reader()
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut buf)
+            .unwrap();
+        assert_eq!(b"contents", &buf[..]);
+        let children = |archive: &Archive<File>, path: &str| {
+            let mut names: Vec<_> = archive
+                .children(path)
+                .iter()
+                .map(|i| archive.files()[*i].name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(vec![Path::new("a"), Path::new("e")], children(&archive, ""));
+        assert_eq!(
+            vec![Path::new("a/b"), Path::new("a/d")],
+            children(&archive, "a")
+        );
+        assert_eq!(vec![Path::new("a/b/c")], children(&archive, "a/b"));
+        assert!(children(&archive, "e").is_empty());
+    }
+
+    #[test]
+    fn xar_extract_to() {
+        use std::os::unix::fs::symlink;
+        use std::os::unix::fs::PermissionsExt;
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        create_dir_all(src_dir.join("a")).unwrap();
+        std::fs::write(src_dir.join("a").join("b"), "contents").unwrap();
+        std::fs::set_permissions(src_dir.join("a"), PermissionsExt::from_mode(0o750)).unwrap();
+        std::fs::set_permissions(
+            src_dir.join("a").join("b"),
+            PermissionsExt::from_mode(0o640),
+        )
+        .unwrap();
+        std::fs::hard_link(src_dir.join("a").join("b"), src_dir.join("c")).unwrap();
+        symlink("a/b", src_dir.join("d")).unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(src_dir.join("a").join("b"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut xar =
+            BuilderOptions::new().create(File::create(&xar_path).unwrap(), None::<NoSigner>);
+        xar.append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        xar.finish().unwrap();
+        let mut archive = Archive::with_options(
+            File::open(&xar_path).unwrap(),
+            ArchiveOptions::new().preserve_mtime(true),
+        )
+        .unwrap();
+        let dest_dir = workdir.path().join("dest");
+        create_dir_all(&dest_dir).unwrap();
+        for (path, new_path) in [("a", "x"), ("a/b", "y"), ("c", "z"), ("d", "w")] {
+            archive
+                .entry_by_path(path)
+                .unwrap()
+                .extract_to(dest_dir.join(new_path))
+                .unwrap();
+        }
+        let metadata = dest_dir.join("x").symlink_metadata().unwrap();
+        assert!(metadata.is_dir());
+        assert_eq!(0o750, metadata.permissions().mode() & 0o7777);
+        for name in ["y", "z"] {
+            let path = dest_dir.join(name);
+            assert_eq!("contents", std::fs::read_to_string(&path).unwrap());
+            let metadata = path.symlink_metadata().unwrap();
+            assert_eq!(0o640, metadata.permissions().mode() & 0o7777);
+            assert_eq!(mtime, metadata.modified().unwrap());
+        }
+        assert_eq!(
+            Path::new("a/b"),
+            std::fs::read_link(dest_dir.join("w")).unwrap()
+        );
+    }
+
     fn test_xar_write_read<S: Signer, V: RootCertVerifier>(
         signer: S,
         root_cert_verifier: V,
@@ -565,7 +1646,12 @@ mod tests {
                 |_file: &xml::File<u64>, _: &Path, _: &Path| Ok(Some(extra)),
             )
             .unwrap();
-            let expected_files = xar.files().to_vec();
+            let expected_files: Vec<_> = xar
+                .files()
+                .iter()
+                .cloned()
+                .flat_map(xml::File::into_vec)
+                .collect();
             xar.finish().unwrap();
             let reader = File::open(&xar_path).unwrap();
             let mut xar_archive = ExtendedArchive::<std::fs::File, u64>::with_root_cert_verifier(
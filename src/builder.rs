@@ -1,11 +1,20 @@
 use std::collections::hash_map::Entry::Occupied;
 use std::collections::hash_map::Entry::Vacant;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::Cursor;
 use std::io::Error;
 use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::io::Write;
+use std::path::Component;
 use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
 use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 use base64ct::Base64;
 use base64ct::Encoding;
@@ -15,19 +24,42 @@ use serde::Serialize;
 use x509_cert::der::Encode;
 use x509_cert::Certificate;
 
+use crate::group_name;
+use crate::user_name;
 use crate::xml;
 use crate::ChecksumAlgo;
 use crate::Compression;
+use crate::Device;
 use crate::File;
+use crate::FileData;
+use crate::FileFlags;
+use crate::FileMode;
 use crate::FileType;
 use crate::HardLink;
+use crate::Link;
+use crate::OwnerMap;
+use crate::PathFilter;
 use crate::Signer;
-use crate::Walk;
+use crate::Timestamp;
+use crate::WalkerOptions;
+use crate::XattrFilter;
 
 /// Builder options.
 pub struct BuilderOptions {
     file_checksum_algo: ChecksumAlgo,
     toc_checksum_algo: ChecksumAlgo,
+    xattrs: bool,
+    xattr_filter: XattrFilter,
+    acls: bool,
+    file_flags: bool,
+    numeric_owner: bool,
+    owner_map: OwnerMap,
+    follow_symlinks: bool,
+    cross_device: bool,
+    path_filter: PathFilter,
+    reproducible: bool,
+    source_date_epoch: Option<SystemTime>,
+    sub_second_timestamps: bool,
 }
 
 impl BuilderOptions {
@@ -36,6 +68,18 @@ impl BuilderOptions {
         Self {
             file_checksum_algo: Default::default(),
             toc_checksum_algo: Default::default(),
+            xattrs: true,
+            xattr_filter: Default::default(),
+            acls: true,
+            file_flags: true,
+            numeric_owner: false,
+            owner_map: Default::default(),
+            follow_symlinks: false,
+            cross_device: false,
+            path_filter: Default::default(),
+            reproducible: false,
+            source_date_epoch: source_date_epoch_from_env(),
+            sub_second_timestamps: false,
         }
     }
 
@@ -51,6 +95,129 @@ impl BuilderOptions {
         self
     }
 
+    /// Archive extended attributes.
+    ///
+    /// `true` by default.
+    pub fn xattrs(mut self, value: bool) -> Self {
+        self.xattrs = value;
+        self
+    }
+
+    /// Archive only the extended attributes selected by the filter.
+    ///
+    /// All attributes are selected by default.
+    pub fn xattr_filter(mut self, filter: XattrFilter) -> Self {
+        self.xattr_filter = filter;
+        self
+    }
+
+    /// Archive POSIX ACLs.
+    ///
+    /// `true` by default.
+    pub fn acls(mut self, value: bool) -> Self {
+        self.acls = value;
+        self
+    }
+
+    /// Archive file flags.
+    ///
+    /// `true` by default.
+    pub fn file_flags(mut self, value: bool) -> Self {
+        self.file_flags = value;
+        self
+    }
+
+    /// Do not record user and group names, only the ids.
+    ///
+    /// `false` by default.
+    pub fn numeric_owner(mut self, value: bool) -> Self {
+        self.numeric_owner = value;
+        self
+    }
+
+    /// Override and remap file owners.
+    ///
+    /// The owners are not changed by default.
+    pub fn owner_map(mut self, map: OwnerMap) -> Self {
+        self.owner_map = map;
+        self
+    }
+
+    /// Archive the files that symbolic links point to instead of the links themselves.
+    ///
+    /// Broken symbolic links are archived as is. Symbolic links to the parent directories are
+    /// archived as empty directories to break the loops.
+    ///
+    /// `false` by default.
+    pub fn follow_symlinks(mut self, value: bool) -> Self {
+        self.follow_symlinks = value;
+        self
+    }
+
+    /// Descend into directories on other file systems.
+    ///
+    /// If disabled, directories that reside on other file systems than the appended directory
+    /// (i.e. mount points) are archived without their contents, the same way tar's
+    /// `--one-file-system` does. When [symbolic links are followed](Self::follow_symlinks), the
+    /// file system of the link's target is checked.
+    ///
+    /// `false` by default.
+    pub fn cross_device(mut self, value: bool) -> Self {
+        self.cross_device = value;
+        self
+    }
+
+    /// Archive only the files selected by the filter.
+    ///
+    /// The filter is matched against archive paths while the directories are walked.
+    /// The contents of the excluded directories are never read. The directories that are not
+    /// selected by the include patterns are archived only if they contain selected files.
+    ///
+    /// All files are selected by default.
+    pub fn path_filter(mut self, filter: PathFilter) -> Self {
+        self.path_filter = filter;
+        self
+    }
+
+    /// Produce byte-identical archives from identical input.
+    ///
+    /// - Directory contents are archived in the order of file names.
+    /// - Archive creation time is set to [`source_date_epoch`](Self::source_date_epoch) or to
+    ///   Unix epoch if it's not set.
+    /// - Modification times later than the source date epoch are clamped to it;
+    ///   access and creation times are set to the modification time.
+    /// - Inode numbers are replaced with sequential numbers and device numbers are zeroed.
+    ///
+    /// File owners are archived as is; use [`owner_map`](Self::owner_map) to make them fixed.
+    ///
+    /// `false` by default.
+    pub fn reproducible(mut self, value: bool) -> Self {
+        self.reproducible = value;
+        self
+    }
+
+    /// The time to use instead of the current time in [`reproducible`](Self::reproducible)
+    /// mode.
+    ///
+    /// By default the value is read from `SOURCE_DATE_EPOCH` environment variable (the number of
+    /// seconds since Unix epoch); invalid values are ignored.
+    pub fn source_date_epoch(mut self, time: Option<SystemTime>) -> Self {
+        self.source_date_epoch = time;
+        self
+    }
+
+    /// Archive timestamps with sub-second precision.
+    ///
+    /// When disabled, the timestamps are rounded down to whole seconds as the entries are
+    /// appended, because other xar implementations (e.g. macOS `xar` and `pkgutil`) only parse
+    /// `%Y-%m-%dT%H:%M:%SZ`.
+    ///
+    /// `false` by default.
+    pub fn sub_second_timestamps(mut self, value: bool) -> Self {
+        self.sub_second_timestamps = value;
+        self
+    }
+
     /// Create new builder using the configured options.
     pub fn create<W: Write, S: Signer, X>(
         self,
@@ -59,6 +226,19 @@ impl BuilderOptions {
     ) -> ExtendedBuilder<W, S, X> {
         ExtendedBuilder::with_options(writer, signer, self)
     }
+
+    /// Create new builder that stores compressed file contents in `heap` using the configured
+    /// options.
+    ///
+    /// See [`ExtendedBuilder::with_heap`].
+    pub fn create_with_heap<W: Write, S: Signer, X, H: Read + Write + Seek>(
+        self,
+        writer: W,
+        signer: Option<S>,
+        heap: H,
+    ) -> ExtendedBuilder<W, S, X, H> {
+        ExtendedBuilder::with_heap(writer, signer, self, heap)
+    }
 }
 
 impl Default for BuilderOptions {
@@ -67,6 +247,33 @@ impl Default for BuilderOptions {
     }
 }
 
+/// Metadata of the entries that are appended to the archive without reading the file system.
+///
+/// See [`ExtendedBuilder::append_file`].
+#[derive(Clone, Debug, Default)]
+pub struct FileMetadata {
+    /// File mode.
+    ///
+    /// Does not include file type.
+    pub mode: FileMode,
+    /// Owner user id.
+    pub uid: u32,
+    /// Owner group id.
+    pub gid: u32,
+    /// Owner user name.
+    pub user: Option<String>,
+    /// Owner group name.
+    pub group: Option<String>,
+    /// Last accessed time.
+    pub atime: Timestamp,
+    /// Last modification time.
+    pub mtime: Timestamp,
+    /// Creation time.
+    pub ctime: Timestamp,
+    /// File flags.
+    pub flags: FileFlags,
+}
+
 /// Signed XAR archive builder without extra data.
 pub type Builder<W, S> = ExtendedBuilder<W, S, ()>;
 
@@ -74,21 +281,71 @@ pub type Builder<W, S> = ExtendedBuilder<W, S, ()>;
 pub type UnsignedBuilder<W> = ExtendedBuilder<W, NoSigner, ()>;
 
 /// XAR archive builder with extra data.
-pub struct ExtendedBuilder<W: Write, S: Signer = NoSigner, X = ()> {
+///
+/// Compressed file contents are accumulated in the heap `H` until the archive is finished.
+/// By default the heap is stored in memory.
+pub struct ExtendedBuilder<
+    W: Write,
+    S: Signer = NoSigner,
+    X = (),
+    H: Read + Write + Seek = Cursor<Vec<u8>>,
+> {
     writer: W,
     signer: Option<S>,
     file_checksum_algo: ChecksumAlgo,
     toc_checksum_algo: ChecksumAlgo,
+    xattrs: bool,
+    xattr_filter: XattrFilter,
+    acls: bool,
+    file_flags: bool,
+    numeric_owner: bool,
+    owner_map: OwnerMap,
+    follow_symlinks: bool,
+    cross_device: bool,
+    path_filter: PathFilter,
+    reproducible: bool,
+    source_date_epoch: Option<SystemTime>,
+    sub_second_timestamps: bool,
     files: Vec<File<X>>,
-    contents: Vec<Vec<u8>>,
-    // (dev, inode) -> file index
-    inodes: HashMap<(u64, u64), usize>,
+    heap: H,
+    // The number of bytes written to the heap so far.
+    heap_len: u64,
+    // (dev, inode) -> file index path in the file tree
+    inodes: HashMap<(u64, u64), Vec<usize>>,
+    // uid -> user name
+    user_names: HashMap<u32, Option<String>>,
+    // gid -> group name
+    group_names: HashMap<u32, Option<String>>,
     offset: u64,
+    next_id: u64,
 }
 
 impl<W: Write, S: Signer, X> ExtendedBuilder<W, S, X> {
     /// Create new archive builder with non-default options.
     pub fn with_options(writer: W, signer: Option<S>, options: BuilderOptions) -> Self {
+        Self::with_heap(writer, signer, options, Cursor::new(Vec::new()))
+    }
+
+    /// Create new archive builder with default options.
+    pub fn new(writer: W, signer: Option<S>) -> Self {
+        Self::with_options(writer, signer, Default::default())
+    }
+
+    /// Create new unsigned archive builder with default options.
+    pub fn new_unsigned(writer: W) -> Self {
+        Self::with_options(writer, None, Default::default())
+    }
+}
+
+impl<W: Write, S: Signer, X, H: Read + Write + Seek> ExtendedBuilder<W, S, X, H> {
+    /// Create new archive builder that stores compressed file contents in `heap`.
+    ///
+    /// The files are compressed as they are read, and the heap is copied to the underlying
+    /// writer when the archive is finished. Use a temporary file as the heap to build archives
+    /// that don't fit into memory: only the table of contents is kept in memory in this case.
+    ///
+    /// The heap is overwritten from the start.
+    pub fn with_heap(writer: W, signer: Option<S>, options: BuilderOptions, heap: H) -> Self {
         let toc_checksum_len = options.toc_checksum_algo.hash_len();
         let offset = if let Some(ref signer) = signer {
             toc_checksum_len + signer.signature_len()
@@ -101,28 +358,38 @@ impl<W: Write, S: Signer, X> ExtendedBuilder<W, S, X> {
             offset: offset as u64,
             file_checksum_algo: options.file_checksum_algo,
             toc_checksum_algo: options.toc_checksum_algo,
+            xattrs: options.xattrs,
+            xattr_filter: options.xattr_filter,
+            acls: options.acls,
+            file_flags: options.file_flags,
+            numeric_owner: options.numeric_owner,
+            owner_map: options.owner_map,
+            follow_symlinks: options.follow_symlinks,
+            cross_device: options.cross_device,
+            path_filter: options.path_filter,
+            reproducible: options.reproducible,
+            source_date_epoch: options.source_date_epoch,
+            sub_second_timestamps: options.sub_second_timestamps,
             files: Default::default(),
-            contents: Default::default(),
+            heap,
+            heap_len: 0,
             inodes: Default::default(),
+            user_names: Default::default(),
+            group_names: Default::default(),
+            next_id: 1,
         }
     }
 
-    /// Create new archive builder with default options.
-    pub fn new(writer: W, signer: Option<S>) -> Self {
-        Self::with_options(writer, signer, Default::default())
-    }
-
-    /// Create new unsigned archive builder with default options.
-    pub fn new_unsigned(writer: W) -> Self {
-        Self::with_options(writer, None, Default::default())
-    }
-
     /// Get the files added so far.
     pub fn files(&self) -> &[File<X>] {
         &self.files[..]
     }
 
     /// Append directory to the archive recursively.
+    ///
+    /// The contents of the directory are placed at the root of the archive.
+    /// The `extra` callback receives the file entry, its archive path and its path in the file
+    /// system and returns the extra data to store along the entry.
     pub fn append_dir_all<F, P>(
         &mut self,
         path: P,
@@ -135,61 +402,431 @@ impl<W: Write, S: Signer, X> ExtendedBuilder<W, S, X> {
         P: AsRef<Path>,
     {
         let path = path.as_ref();
-        let mut next_id = self.files.len() as u64 + 1;
-        let mut next_offset = self.offset;
-        let mut tree = HashMap::new();
-        for entry in path.walk()? {
+        let mut hooks = Hooks::new(
+            |_: &mut File<X>, _: &Path, _: &Path| Ok(true),
+            |file: &mut File<X>, archive_path: &Path, path: &Path| {
+                file.extra = extra(file, archive_path, path)?;
+                Ok(())
+            },
+        );
+        self.append_children(path, path, Path::new(""), &[], compression, &mut hooks)
+    }
+
+    /// Append directory to the archive recursively transforming each entry.
+    ///
+    /// The contents of the directory are placed at the root of the archive.
+    /// The `transform` callback receives the file entry, its archive path and its path in the
+    /// file system before the entry is added to the archive. The callback can modify the
+    /// entry (e.g. change its permissions, set [`extra`](File::extra) data or rename it by
+    /// changing its [`name`](File::name), i.e. the last component of the archive path) and
+    /// returns `false` to skip the entry. The contents of the skipped directories are skipped as
+    /// well.
+    ///
+    /// The callback is called before the file contents and extended attributes are read, i.e.
+    /// the skipped files are never read and [`data`](File::data) and [`xattrs`](File::xattrs) of
+    /// the entry are always empty. Fails if the new name contains `/` or is the same as the name
+    /// of another entry in the same directory.
+    pub fn append_dir_all_with<F, P>(
+        &mut self,
+        path: P,
+        compression: Compression,
+        transform: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&mut File<X>, &Path, &Path) -> Result<bool, Error>,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut hooks = Hooks::new(transform, |_: &mut File<X>, _: &Path, _: &Path| Ok(()));
+        self.append_children(path, path, Path::new(""), &[], compression, &mut hooks)
+    }
+
+    /// Append file or directory at `path` to the archive as `archive_path`.
+    ///
+    /// Directories are appended recursively. The missing parent directories of `archive_path`
+    /// are created with default metadata and `0o755` mode (see [`append_dir`](Self::append_dir)).
+    /// Fails if the entry with the same archive path already exists.
+    pub fn append_path_with_name<P1, P2>(
+        &mut self,
+        path: P1,
+        archive_path: P2,
+        compression: Compression,
+    ) -> Result<(), Error>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let archive_path = archive_path.as_ref();
+        if self.path_filter.excludes(archive_path) {
+            return Ok(());
+        }
+        let (parent, name) = self.create_parent_dirs(archive_path, &Default::default())?;
+        self.check_duplicate(&parent, &name)?;
+        let archive_path: PathBuf = archive_path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(..)))
+            .collect();
+        let mut hooks = Hooks::new(
+            |_: &mut File<X>, _: &Path, _: &Path| Ok(true),
+            |_: &mut File<X>, _: &Path, _: &Path| Ok(()),
+        );
+        let Some(index_path) =
+            self.append_entry(path, path, &archive_path, &parent, compression, &mut hooks)?
+        else {
+            return Ok(());
+        };
+        let is_dir = if self.follow_symlinks {
+            path.is_dir()
+        } else {
+            path.symlink_metadata()?.is_dir()
+        };
+        if is_dir {
+            self.append_children(
+                path,
+                path,
+                &archive_path,
+                &index_path,
+                compression,
+                &mut hooks,
+            )?;
+        }
+        Ok(())
+    }
+
+    // Append the contents of the directory at `path` to the directory at `archive_path` with
+    // `index_path` in the file tree.
+    fn append_children<F, G>(
+        &mut self,
+        prefix: &Path,
+        path: &Path,
+        archive_path: &Path,
+        index_path: &[usize],
+        compression: Compression,
+        hooks: &mut Hooks<F, G>,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&mut File<X>, &Path, &Path) -> Result<bool, Error>,
+        G: FnMut(&mut File<X>, &Path, &Path) -> Result<(), Error>,
+    {
+        let mut dirs = WalkedDirs::default();
+        dirs.selected
+            .insert(archive_path.to_path_buf(), index_path.to_vec());
+        let mut walker = WalkerOptions::default()
+            .follow_symlinks(self.follow_symlinks)
+            .cross_device(self.cross_device)
+            .sort(self.reproducible)
+            .walk(path)?;
+        while let Some(entry) = walker.next() {
             let entry = entry?;
-            let archive_path = entry
+            let relative_path = entry
                 .path()
                 .strip_prefix(path)
                 .map_err(|_| ErrorKind::InvalidData)?
                 .normalize();
-            if archive_path == Path::new("") {
+            if relative_path == Path::new("") {
                 continue;
             }
-            let (file, archived_contents) = File::<X>::new(
-                next_id,
-                path,
-                entry.path(),
-                Path::new(archive_path.file_name().unwrap_or_default()).to_path_buf(),
+            let entry_archive_path = archive_path.join(relative_path);
+            let file_type = entry.file_type()?;
+            let is_dir = file_type.is_dir()
+                || (self.follow_symlinks && file_type.is_symlink() && entry.path().is_dir());
+            if self.path_filter.excludes(&entry_archive_path) {
+                if is_dir {
+                    walker.skip_current_dir();
+                }
+                continue;
+            }
+            if !self.path_filter.includes(&entry_archive_path) {
+                // The directory is archived only if it contains selected files.
+                if is_dir {
+                    dirs.unselected.insert(entry_archive_path, entry.path());
+                }
+                continue;
+            }
+            let parent = entry_archive_path.parent().unwrap_or(Path::new(""));
+            let parent = self.find_parent_dir(parent, &mut dirs, prefix, compression, hooks)?;
+            let index_path = match parent {
+                Some(parent) => self.append_entry(
+                    prefix,
+                    &entry.path(),
+                    &entry_archive_path,
+                    &parent,
+                    compression,
+                    hooks,
+                )?,
+                None => None,
+            };
+            match index_path {
+                Some(index_path) if is_dir => {
+                    dirs.selected.insert(entry_archive_path, index_path);
+                }
+                Some(..) => {}
+                None if is_dir => {
+                    dirs.skipped.insert(entry_archive_path);
+                    walker.skip_current_dir();
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Find the index path of the directory at `archive_path` appending the directory and its
+    // parents if they were not selected by the filter.
+    //
+    // Returns `None` if the directory was skipped by `transform`.
+    fn find_parent_dir<F, G>(
+        &mut self,
+        archive_path: &Path,
+        dirs: &mut WalkedDirs,
+        prefix: &Path,
+        compression: Compression,
+        hooks: &mut Hooks<F, G>,
+    ) -> Result<Option<Vec<usize>>, Error>
+    where
+        F: FnMut(&mut File<X>, &Path, &Path) -> Result<bool, Error>,
+        G: FnMut(&mut File<X>, &Path, &Path) -> Result<(), Error>,
+    {
+        if let Some(index_path) = dirs.selected.get(archive_path) {
+            return Ok(Some(index_path.clone()));
+        }
+        if dirs.skipped.contains(archive_path) {
+            return Ok(None);
+        }
+        // The walk is breadth-first, i.e. parent directories always precede their children.
+        let path = dirs
+            .unselected
+            .remove(archive_path)
+            .ok_or(ErrorKind::InvalidData)?;
+        let parent = archive_path.parent().unwrap_or(Path::new(""));
+        let index_path = match self.find_parent_dir(parent, dirs, prefix, compression, hooks)? {
+            Some(parent) => {
+                self.append_entry(prefix, &path, archive_path, &parent, compression, hooks)?
+            }
+            None => None,
+        };
+        match index_path {
+            Some(ref index_path) => {
+                dirs.selected
+                    .insert(archive_path.to_path_buf(), index_path.clone());
+            }
+            None => {
+                dirs.skipped.insert(archive_path.to_path_buf());
+            }
+        }
+        Ok(index_path)
+    }
+
+    // Append the file at `path` as the last child of the directory at `parent` index path.
+    //
+    // The contents are read only if the entry is not skipped by `transform`.
+    // Returns the index path of the new entry or `None` if the entry was skipped.
+    fn append_entry<F, G>(
+        &mut self,
+        prefix: &Path,
+        path: &Path,
+        archive_path: &Path,
+        parent: &[usize],
+        compression: Compression,
+        hooks: &mut Hooks<F, G>,
+    ) -> Result<Option<Vec<usize>>, Error>
+    where
+        F: FnMut(&mut File<X>, &Path, &Path) -> Result<bool, Error>,
+        G: FnMut(&mut File<X>, &Path, &Path) -> Result<(), Error>,
+    {
+        // Read the symbolic link target instead of the link itself (unless the link is broken).
+        let source = if self.follow_symlinks && path.symlink_metadata()?.is_symlink() {
+            path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+        } else {
+            path.to_path_buf()
+        };
+        let name = Path::new(archive_path.file_name().unwrap_or_default()).to_path_buf();
+        let mut file = File::<X>::from_path(self.next_id, prefix, &source, name.clone(), None)?;
+        if !self.numeric_owner {
+            file.user = self
+                .user_names
+                .entry(file.uid)
+                .or_insert_with_key(|uid| user_name(*uid).unwrap_or_default())
+                .clone();
+            file.group = self
+                .group_names
+                .entry(file.gid)
+                .or_insert_with_key(|gid| group_name(*gid).unwrap_or_default())
+                .clone();
+        }
+        self.owner_map.apply(&mut file);
+        if !(hooks.transform)(&mut file, archive_path, path)? {
+            return Ok(None);
+        }
+        // The names found during the walk are unique, i.e. only the renamed entries need to be
+        // checked against their siblings.
+        if file.name != name {
+            check_file_name(&file.name)?;
+            self.check_duplicate(parent, &file.name)?;
+            hooks.renamed.insert((parent.to_vec(), file.name.clone()));
+        } else if !hooks.renamed.is_empty() && hooks.renamed.contains(&(parent.to_vec(), name)) {
+            return Err(duplicate_entry(&file.name));
+        }
+        // The contents of the failed entries are overwritten by the next entry.
+        self.heap.seek(SeekFrom::Start(self.heap_len))?;
+        file.read_contents(
+            &source,
+            compression,
+            self.file_checksum_algo,
+            self.offset,
+            &mut self.heap,
+        )?;
+        let mut archived_len = file.data().map_or(0, |data| data.length);
+        if self.xattrs {
+            let archived_xattrs = file.read_xattrs(
+                &source,
+                &self.xattr_filter,
                 compression,
                 self.file_checksum_algo,
-                next_offset,
-                None,
+                self.offset + archived_len,
             )?;
-            next_id += 1;
-            next_offset += archived_contents.len() as u64;
-            let parent = archive_path
-                .parent()
-                .map(|x| x.to_path_buf())
-                .unwrap_or_default();
-            if parent == Path::new("") {
-                tree.insert(archive_path, (file, archived_contents, entry.path()));
-                continue;
-            }
-            let parent = tree.get_mut(&parent).ok_or(ErrorKind::InvalidData)?;
-            parent.0.children.push(file);
+            self.heap.write_all(&archived_xattrs)?;
+            archived_len += archived_xattrs.len() as u64;
         }
-        let mut files: Vec<_> = tree.into_iter().collect();
-        files.sort_unstable_by_key(|entry| entry.1 .0.id);
-        for (archive_path, (mut file, archived_contents, real_path)) in files.into_iter() {
-            file.extra = extra(&file, &archive_path, &real_path)?;
-            self.append_raw(file, archived_contents)?;
+        if self.acls {
+            file.read_acl(&source)?;
         }
-        Ok(())
+        if self.file_flags {
+            file.read_flags(&source)?;
+        }
+        (hooks.finish)(&mut file, archive_path, path)?;
+        let index_path = self.insert(parent, file, archived_len)?;
+        Ok(Some(index_path))
     }
 
-    /// Append raw entry to the archive.
-    pub fn append_raw(
+    /// Append regular file with the specified metadata to the archive.
+    ///
+    /// The contents are read from `reader` (e.g. a byte slice) and are compressed as they are
+    /// read. The missing parent directories of `path` are created with the same metadata and
+    /// `0o755` mode. Fails if the entry with the same path already exists.
+    ///
+    /// Returns the new entry.
+    pub fn append_file<P: AsRef<Path>, R: Read>(
         &mut self,
-        mut file: File<X>,
-        archived_contents: Vec<u8>,
-    ) -> Result<(), Error> {
-        self.handle_hard_links(&mut file);
-        self.offset += archived_contents.len() as u64;
-        self.files.push(file);
-        self.contents.push(archived_contents);
+        path: P,
+        metadata: &FileMetadata,
+        compression: Compression,
+        reader: R,
+    ) -> Result<&mut File<X>, Error> {
+        let (parent, name) = self.create_parent_dirs(path.as_ref(), metadata)?;
+        self.heap.seek(SeekFrom::Start(self.heap_len))?;
+        let data = FileData::encode_reader(
+            reader,
+            compression,
+            self.file_checksum_algo,
+            self.offset,
+            &mut self.heap,
+        )?;
+        let mut file = self.new_file(name, FileType::File, metadata);
+        let archived_len = if data.size != 0 { data.length } else { 0 };
+        file.data = (data.size != 0).then_some(data);
+        self.append_new(&parent, file, archived_len)
+    }
+
+    /// Append directory with the specified metadata to the archive.
+    ///
+    /// The missing parent directories of `path` are created with the same metadata and `0o755`
+    /// mode. If the directory already exists, its metadata is replaced.
+    ///
+    /// Returns the directory entry.
+    pub fn append_dir<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        metadata: &FileMetadata,
+    ) -> Result<&mut File<X>, Error> {
+        let (parent, name) = self.create_parent_dirs(path.as_ref(), metadata)?;
+        let file = self.new_file(name, FileType::Directory, metadata);
+        let Some(i) = self
+            .children_mut(&parent)?
+            .iter()
+            .position(|sibling| sibling.name == file.name && sibling.kind == FileType::Directory)
+        else {
+            return self.append_new(&parent, file, 0);
+        };
+        let dir = &mut self.children_mut(&parent)?[i];
+        dir.mode = file.mode;
+        dir.uid = file.uid;
+        dir.gid = file.gid;
+        dir.user = file.user;
+        dir.group = file.group;
+        dir.atime = file.atime;
+        dir.mtime = file.mtime;
+        dir.ctime = file.ctime;
+        dir.flags = file.flags;
+        Ok(dir)
+    }
+
+    /// Append symbolic link with the specified metadata to the archive.
+    ///
+    /// The missing parent directories of `path` are created with the same metadata and `0o755`
+    /// mode. Fails if the entry with the same path already exists.
+    ///
+    /// Returns the new entry.
+    pub fn append_symlink<P1: AsRef<Path>, P2: AsRef<Path>>(
+        &mut self,
+        path: P1,
+        metadata: &FileMetadata,
+        target: P2,
+    ) -> Result<&mut File<X>, Error> {
+        let (parent, name) = self.create_parent_dirs(path.as_ref(), metadata)?;
+        let mut file = self.new_file(name, FileType::Symlink, metadata);
+        file.link = Some(Link {
+            kind: xml::SYMLINK_FILE.into(),
+            target: target.as_ref().to_path_buf(),
+        });
+        self.append_new(&parent, file, 0)
+    }
+
+    /// Append FIFO, socket, character or block device with the specified metadata to the
+    /// archive.
+    ///
+    /// `device` is required for character and block devices and is ignored otherwise.
+    /// The missing parent directories of `path` are created with the same metadata and `0o755`
+    /// mode. Fails if the entry with the same path already exists.
+    ///
+    /// Returns the new entry.
+    pub fn append_special<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        metadata: &FileMetadata,
+        kind: FileType,
+        device: Option<Device>,
+    ) -> Result<&mut File<X>, Error> {
+        let device = match kind {
+            FileType::CharacterSpecial | FileType::BlockSpecial => {
+                Some(device.ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "device numbers are required")
+                })?)
+            }
+            FileType::Fifo | FileType::Socket => None,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("not a special file type: {kind:?}"),
+                ))
+            }
+        };
+        let (parent, name) = self.create_parent_dirs(path.as_ref(), metadata)?;
+        let mut file = self.new_file(name, kind, metadata);
+        file.device = device;
+        self.append_new(&parent, file, 0)
+    }
+
+    /// Append raw entry to the archive.
+    ///
+    /// The entry is placed at the root of the archive.
+    /// Its `archived_contents` should include the contents of all its children
+    /// in the order of their offsets.
+    pub fn append_raw(&mut self, file: File<X>, archived_contents: Vec<u8>) -> Result<(), Error> {
+        self.heap.seek(SeekFrom::Start(self.heap_len))?;
+        self.heap.write_all(&archived_contents)?;
+        self.insert(&[], file, archived_contents.len() as u64)?;
         Ok(())
     }
 
@@ -203,27 +840,309 @@ impl<W: Write, S: Signer, X> ExtendedBuilder<W, S, X> {
         &self.writer
     }
 
-    fn handle_hard_links(&mut self, file: &mut File<X>) {
-        match self.inodes.entry((file.deviceno, file.inode)) {
-            Vacant(v) => {
-                let i = self.files.len();
-                v.insert(i);
+    // Create new entry that doesn't exist in the file system.
+    fn new_file(&self, name: PathBuf, kind: FileType, metadata: &FileMetadata) -> File<X> {
+        let mut file = File::empty(self.next_id, name, kind);
+        // Inode numbers are unique on device 0, i.e. the entries are never treated as hard links.
+        file.inode = file.id;
+        file.mode = metadata.mode;
+        file.uid = metadata.uid;
+        file.gid = metadata.gid;
+        if !self.numeric_owner {
+            file.user = metadata.user.clone();
+            file.group = metadata.group.clone();
+        }
+        file.atime = metadata.atime;
+        file.mtime = metadata.mtime;
+        file.ctime = metadata.ctime;
+        file.flags = metadata.flags;
+        self.owner_map.apply(&mut file);
+        file
+    }
+
+    // Find the parent directory of `path` creating the missing directories along the way.
+    //
+    // Returns the index path of the parent directory and the file name.
+    fn create_parent_dirs(
+        &mut self,
+        path: &Path,
+        metadata: &FileMetadata,
+    ) -> Result<(Vec<usize>, PathBuf), Error> {
+        let mut names = Vec::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => names.push(name),
+                Component::RootDir | Component::CurDir => {}
+                Component::ParentDir | Component::Prefix(..) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("invalid archive path: {}", path.display()),
+                    ))
+                }
             }
-            Occupied(o) => {
-                let i = *o.get();
-                let original_file = &mut self.files[i];
-                file.kind = FileType::HardLink(HardLink::Id(original_file.id));
+        }
+        let Some((name, dir_names)) = names.split_last() else {
+            return Err(Error::new(ErrorKind::InvalidInput, "empty archive path"));
+        };
+        let dir_metadata = FileMetadata {
+            mode: 0o755.into(),
+            ..metadata.clone()
+        };
+        let mut index_path = Vec::new();
+        for dir_name in dir_names.iter() {
+            let siblings = self.children_mut(&index_path)?;
+            match siblings
+                .iter()
+                .position(|file| file.name == Path::new(dir_name))
+            {
+                Some(i) if siblings[i].kind == FileType::Directory => index_path.push(i),
+                Some(_) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("not a directory: {}", dir_name.display()),
+                    ))
+                }
+                None => {
+                    let dir = self.new_file(dir_name.into(), FileType::Directory, &dir_metadata);
+                    index_path = self.insert(&index_path, dir, 0)?;
+                }
+            }
+        }
+        Ok((index_path, name.into()))
+    }
+
+    // Insert new entry that doesn't exist in the archive yet and return it.
+    fn append_new(
+        &mut self,
+        parent: &[usize],
+        file: File<X>,
+        archived_len: u64,
+    ) -> Result<&mut File<X>, Error> {
+        self.check_duplicate(parent, &file.name)?;
+        let index_path = self.insert(parent, file, archived_len)?;
+        get_file_mut(&mut self.files, &index_path).ok_or_else(|| ErrorKind::InvalidData.into())
+    }
+
+    // Fail if the directory at `parent` index path already contains the entry with this name.
+    fn check_duplicate(&mut self, parent: &[usize], name: &Path) -> Result<(), Error> {
+        if self
+            .children_mut(parent)?
+            .iter()
+            .any(|sibling| sibling.name == name)
+        {
+            return Err(duplicate_entry(name));
+        }
+        Ok(())
+    }
+
+    // Get the children of the directory at `index_path` or the root entries if the path is
+    // empty.
+    fn children_mut(&mut self, index_path: &[usize]) -> Result<&mut Vec<File<X>>, Error> {
+        if index_path.is_empty() {
+            return Ok(&mut self.files);
+        }
+        Ok(&mut get_file_mut(&mut self.files, index_path)
+            .ok_or(ErrorKind::InvalidData)?
+            .children)
+    }
+
+    // Insert the file as the last child of the directory at `parent` index path
+    // and return the index path of the file.
+    //
+    // The archived contents of the file should already be written to the heap.
+    fn insert(
+        &mut self,
+        parent: &[usize],
+        mut file: File<X>,
+        archived_len: u64,
+    ) -> Result<Vec<usize>, Error> {
+        if !self.sub_second_timestamps {
+            truncate_timestamps(std::slice::from_mut(&mut file));
+        }
+        let siblings = if parent.is_empty() {
+            &mut self.files
+        } else {
+            &mut get_file_mut(&mut self.files, parent)
+                .ok_or(ErrorKind::InvalidData)?
+                .children
+        };
+        let mut index_path = parent.to_vec();
+        index_path.push(siblings.len());
+        self.next_id = self.next_id.max(max_id(&file) + 1);
+        siblings.push(file);
+        self.handle_hard_links(&index_path)?;
+        self.offset += archived_len;
+        self.heap_len += archived_len;
+        Ok(index_path)
+    }
+
+    fn handle_hard_links(&mut self, index_path: &[usize]) -> Result<(), Error> {
+        let file = get_file_mut(&mut self.files, index_path).ok_or(ErrorKind::InvalidData)?;
+        let num_children = file.children.len();
+        // Directories can't be hard-linked but the same directory can be reached via symbolic
+        // links when they are followed.
+        let key = (file.kind != FileType::Directory).then_some((file.deviceno, file.inode));
+        match key.map(|key| self.inodes.entry(key)) {
+            None => {}
+            Some(Vacant(v)) => {
+                v.insert(index_path.to_vec());
+            }
+            Some(Occupied(o)) => {
+                let original_file =
+                    get_file_mut(&mut self.files, o.get()).ok_or(ErrorKind::InvalidData)?;
                 // Do not overwrite original file type if it is already `HardLink`.
                 if !matches!(original_file.kind, FileType::HardLink(..)) {
                     original_file.kind = FileType::HardLink(HardLink::Original);
                 }
+                let original_id = original_file.id;
+                let file =
+                    get_file_mut(&mut self.files, index_path).ok_or(ErrorKind::InvalidData)?;
+                file.kind = FileType::HardLink(HardLink::Id(original_id));
+            }
+        }
+        let mut child_index_path = index_path.to_vec();
+        child_index_path.push(0);
+        for i in 0..num_children {
+            if let Some(last) = child_index_path.last_mut() {
+                *last = i;
             }
+            self.handle_hard_links(&child_index_path)?;
+        }
+        Ok(())
+    }
+}
+
+// The callbacks that are called for each entry found during the walk.
+struct Hooks<F, G> {
+    // Called before the contents are read, returns `false` to skip the entry.
+    transform: F,
+    // Called after the contents are read.
+    finish: G,
+    // (parent index path, name) of the entries renamed by `transform`
+    renamed: HashSet<(Vec<usize>, PathBuf)>,
+}
+
+impl<F, G> Hooks<F, G> {
+    fn new(transform: F, finish: G) -> Self {
+        Self {
+            transform,
+            finish,
+            renamed: Default::default(),
         }
     }
 }
 
-impl<W: Write, S: Signer, X: Serialize + for<'a> Deserialize<'a> + Default>
-    ExtendedBuilder<W, S, X>
+fn duplicate_entry(name: &Path) -> Error {
+    Error::new(
+        ErrorKind::AlreadyExists,
+        format!("duplicate archive entry: {}", name.display()),
+    )
+}
+
+// Check that the entry name is a single path component.
+fn check_file_name(name: &Path) -> Result<(), Error> {
+    let mut components = name.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(component)), None) if component == name.as_os_str() => Ok(()),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid file name: {}", name.display()),
+        )),
+    }
+}
+
+// The directories found during the walk.
+#[derive(Default)]
+struct WalkedDirs {
+    // archive path -> directory index path in the file tree
+    selected: HashMap<PathBuf, Vec<usize>>,
+    // archive path -> file system path of the directories that are not selected by the filter
+    unselected: HashMap<PathBuf, PathBuf>,
+    // archive paths of the directories that were skipped by the `transform` callback
+    skipped: HashSet<PathBuf>,
+}
+
+// Make the file entries independent of the build host and the build time.
+//
+// The inode numbers are assigned in the order of the entries in the tree, so that the hard links
+// still share the same number.
+fn make_reproducible<X>(
+    files: &mut [File<X>],
+    source_date_epoch: Option<SystemTime>,
+    // (dev, inode) -> new inode
+    inodes: &mut HashMap<(u64, u64), u64>,
+) {
+    for file in files.iter_mut() {
+        let next_inode = inodes.len() as u64 + 1;
+        file.inode = *inodes
+            .entry((file.deviceno, file.inode))
+            .or_insert(next_inode);
+        file.deviceno = 0;
+        if let Some(source_date_epoch) = source_date_epoch {
+            file.mtime.0 = file.mtime.0.min(source_date_epoch);
+        }
+        file.atime = file.mtime;
+        file.ctime = file.mtime;
+        make_reproducible(&mut file.children, source_date_epoch, inodes);
+    }
+}
+
+fn truncate_timestamps<X>(files: &mut [File<X>]) {
+    for file in files.iter_mut() {
+        file.atime.0 = whole_seconds(file.atime.0);
+        file.mtime.0 = whole_seconds(file.mtime.0);
+        file.ctime.0 = whole_seconds(file.ctime.0);
+        truncate_timestamps(&mut file.children);
+    }
+}
+
+// Round the time down to whole seconds.
+fn whole_seconds(t: SystemTime) -> SystemTime {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => UNIX_EPOCH + Duration::from_secs(d.as_secs()),
+        Err(e) => {
+            let d = e.duration();
+            let secs = d.as_secs() + u64::from(d.subsec_nanos() != 0);
+            UNIX_EPOCH
+                .checked_sub(Duration::from_secs(secs))
+                .unwrap_or(t)
+        }
+    }
+}
+
+fn source_date_epoch_from_env() -> Option<SystemTime> {
+    let secs: u64 = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+fn get_file_mut<'a, X>(
+    mut files: &'a mut [File<X>],
+    index_path: &[usize],
+) -> Option<&'a mut File<X>> {
+    let (last, init) = index_path.split_last()?;
+    for i in init.iter() {
+        files = &mut files.get_mut(*i)?.children;
+    }
+    files.get_mut(*last)
+}
+
+fn max_id<X>(file: &File<X>) -> u64 {
+    file.children
+        .iter()
+        .map(max_id)
+        .fold(file.id, |a, b| a.max(b))
+}
+
+impl<
+        W: Write,
+        S: Signer,
+        X: Serialize + for<'a> Deserialize<'a> + Default,
+        H: Read + Write + Seek,
+    > ExtendedBuilder<W, S, X, H>
 {
     /// Write the archive to the underlying writer.
     pub fn finish(mut self) -> Result<W, Error> {
@@ -250,6 +1169,21 @@ impl<W: Write, S: Signer, X: Serialize + for<'a> Deserialize<'a> + Default>
             }),
             None => None,
         };
+        let creation_time = if self.reproducible {
+            make_reproducible(
+                &mut self.files,
+                self.source_date_epoch,
+                &mut Default::default(),
+            );
+            self.source_date_epoch.unwrap_or(UNIX_EPOCH)
+        } else {
+            SystemTime::now()
+        };
+        let creation_time = if self.sub_second_timestamps {
+            creation_time
+        } else {
+            whole_seconds(creation_time)
+        };
         let xar = xml::Xar::<X> {
             toc: xml::Toc::<X> {
                 checksum: xml::TocChecksum {
@@ -259,7 +1193,7 @@ impl<W: Write, S: Signer, X: Serialize + for<'a> Deserialize<'a> + Default>
                 },
                 files: self.files,
                 signature,
-                creation_time: xml::Timestamp(SystemTime::now()),
+                creation_time: xml::Timestamp(creation_time),
             },
         };
         // write header and toc
@@ -268,8 +1202,14 @@ impl<W: Write, S: Signer, X: Serialize + for<'a> Deserialize<'a> + Default>
             self.toc_checksum_algo,
             self.signer.as_ref(),
         )?;
-        for content in self.contents.into_iter() {
-            self.writer.write_all(&content)?;
+        // write heap
+        self.heap.seek(SeekFrom::Start(0))?;
+        let n = std::io::copy(&mut (&mut self.heap).take(self.heap_len), &mut self.writer)?;
+        if n != self.heap_len {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "the heap was truncated while the archive was being built",
+            ));
         }
         Ok(self.writer)
     }
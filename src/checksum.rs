@@ -2,6 +2,7 @@ use std::fmt::Display;
 use std::fmt::Formatter;
 use std::io::Error;
 use std::io::ErrorKind;
+use std::io::Read;
 use std::str::FromStr;
 
 use base16ct::HexDisplay;
@@ -154,6 +155,11 @@ impl ChecksumAlgo {
         Checksum::compute(self, data)
     }
 
+    /// Create new incremental hasher.
+    pub fn hasher(self) -> Hasher {
+        Hasher::new(self)
+    }
+
     /// Get hash size.
     pub fn hash_len(self) -> usize {
         use ChecksumAlgo::*;
@@ -187,6 +193,181 @@ impl TryFrom<u32> for ChecksumAlgo {
     }
 }
 
+/// Incremental hash computation.
+#[derive(Clone)]
+pub enum Hasher {
+    /// No hashing.
+    None,
+    /// MD5 hash.
+    Md5(md5::Context),
+    /// SHA1 hash.
+    Sha1(Sha1),
+    /// SHA256 hash.
+    Sha256(Sha256),
+    /// SHA512 hash.
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    /// Create new hasher for the specified algorithm.
+    pub fn new(algo: ChecksumAlgo) -> Self {
+        match algo {
+            ChecksumAlgo::None => Self::None,
+            ChecksumAlgo::Md5 => Self::Md5(md5::Context::new()),
+            ChecksumAlgo::Sha1 => Self::Sha1(Sha1::new()),
+            ChecksumAlgo::Sha256 => Self::Sha256(Sha256::new()),
+            ChecksumAlgo::Sha512 => Self::Sha512(Sha512::new()),
+        }
+    }
+
+    /// Hash the next chunk of data.
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::None => {}
+            Self::Md5(h) => h.consume(data),
+            Self::Sha1(h) => h.update(data),
+            Self::Sha256(h) => h.update(data),
+            Self::Sha512(h) => h.update(data),
+        }
+    }
+
+    /// Get the hash of all the data.
+    pub fn finalize(self) -> Checksum {
+        match self {
+            Self::None => Checksum::None,
+            Self::Md5(h) => Checksum::Md5(h.finalize().into()),
+            Self::Sha1(h) => Checksum::Sha1(h.finalize().into()),
+            Self::Sha256(h) => Checksum::Sha256(h.finalize().into()),
+            Self::Sha512(h) => Checksum::Sha512(h.finalize().into()),
+        }
+    }
+}
+
+/// A reader that verifies the checksum of the data while it is being read.
+///
+/// The checksum and the length of the data are verified when the underlying reader reaches the
+/// end of the stream, i.e. the data has to be read until [`read`](Read::read) returns zero bytes.
+/// On checksum mismatch the reader returns an error that wraps [`ChecksumMismatch`].
+/// If the stream is shorter or longer than the expected length, an error with
+/// [`InvalidData`](ErrorKind::InvalidData) kind is returned.
+pub struct ChecksumReader<R> {
+    inner: R,
+    hasher: Option<Hasher>,
+    expected: Checksum,
+    length: u64,
+    // the number of bytes read so far
+    count: u64,
+    // the end of the stream has been reached and the checksum has been verified
+    verified: bool,
+    failed: bool,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    /// Create new reader that expects `length` bytes with `expected` checksum.
+    ///
+    /// If `expected` is `None` neither the checksum nor the length is verified.
+    pub fn new(inner: R, expected: Option<Checksum>, length: u64) -> Self {
+        let (hasher, expected) = match expected {
+            Some(expected) => (Some(expected.algo().hasher()), expected),
+            None => (None, Checksum::None),
+        };
+        Self {
+            inner,
+            hasher,
+            expected,
+            length,
+            count: 0,
+            verified: false,
+            failed: false,
+        }
+    }
+
+    /// Get the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn update(&mut self, data: &[u8], eof: bool) -> Result<(), Error> {
+        let Some(hasher) = self.hasher.as_mut() else {
+            return Ok(());
+        };
+        hasher.update(data);
+        self.count += data.len() as u64;
+        if self.count > self.length {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("expected {} bytes, got more", self.length),
+            ));
+        }
+        if !eof || self.verified {
+            return Ok(());
+        }
+        if self.count != self.length {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("expected {} bytes, got {}", self.length, self.count),
+            ));
+        }
+        self.verified = true;
+        let actual = std::mem::replace(hasher, Hasher::None).finalize();
+        if actual != self.expected {
+            return Err(ChecksumMismatch {
+                expected: self.expected.clone(),
+                actual,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.failed {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "checksum verification failed",
+            ));
+        }
+        let n = self.inner.read(buf)?;
+        if let Err(e) = self.update(&buf[..n], n == 0 && !buf.is_empty()) {
+            self.failed = true;
+            return Err(e);
+        }
+        Ok(n)
+    }
+}
+
+/// Checksum mismatch error.
+///
+/// Returned by [`ChecksumReader`] wrapped in [`std::io::Error`] with
+/// [`InvalidData`](std::io::ErrorKind::InvalidData) kind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    /// The checksum stored in the archive.
+    pub expected: Checksum,
+    /// The checksum of the data that was actually read.
+    pub actual: Checksum,
+}
+
+impl Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "checksum mismatch: expected {}, actual {}",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+impl From<ChecksumMismatch> for Error {
+    fn from(other: ChecksumMismatch) -> Self {
+        Error::new(ErrorKind::InvalidData, other)
+    }
+}
+
 const MD5_LEN: usize = 16;
 const SHA1_LEN: usize = 20;
 const SHA256_LEN: usize = 32;
@@ -252,6 +433,64 @@ mod tests {
         });
     }
 
+    #[test]
+    fn checksum_reader() {
+        arbtest(|u| {
+            let algo: ChecksumAlgo = u.arbitrary()?;
+            let mut data: Vec<u8> = u.arbitrary()?;
+            let expected = algo.hash(&data);
+            let mut reader =
+                ChecksumReader::new(&data[..], Some(expected.clone()), data.len() as u64);
+            let mut actual = Vec::new();
+            reader.read_to_end(&mut actual).unwrap();
+            assert_eq!(data, actual);
+            if algo == ChecksumAlgo::None || data.is_empty() {
+                return Ok(());
+            }
+            let i = u.choose_index(data.len())?;
+            data[i] = data[i].wrapping_add(1);
+            let mut reader = ChecksumReader::new(&data[..], Some(expected), data.len() as u64);
+            let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+            assert_eq!(ErrorKind::InvalidData, error.kind());
+            assert!(error
+                .into_inner()
+                .unwrap()
+                .downcast::<ChecksumMismatch>()
+                .is_ok());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn checksum_reader_length_mismatch() {
+        arbtest(|u| {
+            let algo: ChecksumAlgo = u.arbitrary()?;
+            let data: Vec<u8> = u.arbitrary()?;
+            if data.is_empty() {
+                return Ok(());
+            }
+            let expected = algo.hash(&data);
+            // trailing bytes
+            let mut longer = data.clone();
+            longer.push(0);
+            let mut reader =
+                ChecksumReader::new(&longer[..], Some(expected.clone()), data.len() as u64);
+            let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+            assert_eq!(ErrorKind::InvalidData, error.kind());
+            // further reads fail as well
+            assert!(reader.read(&mut [0_u8; 1]).is_err());
+            // short read
+            let i = u.choose_index(data.len())?;
+            let mut reader = ChecksumReader::new(&data[..i], Some(expected), data.len() as u64);
+            let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+            assert_eq!(ErrorKind::InvalidData, error.kind());
+            // no verification
+            let mut reader = ChecksumReader::new(&longer[..], None, data.len() as u64);
+            reader.read_to_end(&mut Vec::new()).unwrap();
+            Ok(())
+        });
+    }
+
     #[test]
     fn try_from_u32_into_u32_symmetry() {
         arbtest(|u| {
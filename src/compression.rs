@@ -80,14 +80,35 @@ pub enum XarDecoder<R: Read> {
     Xz(XzDecoder<R>),
 }
 
+impl<R: Read> XarDecoder<R> {
+    /// Get mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        match self {
+            Self::OctetStream(r) => r,
+            Self::Gzip(r) => r.get_mut(),
+            Self::Bzip2(r) => r.get_mut(),
+            Self::Xz(r) => r.get_mut(),
+        }
+    }
+}
+
 impl<R: Read> Read for XarDecoder<R> {
+    /// Decompress the data.
+    ///
+    /// The underlying reader is read until the end when the decoder reaches the end of the
+    /// compressed stream, i.e. the trailing data is never left unread and a checksum of the
+    /// whole compressed contents can be verified.
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        match self {
-            Self::OctetStream(r) => r.read(buf),
-            Self::Gzip(r) => r.read(buf),
-            Self::Bzip2(r) => r.read(buf),
-            Self::Xz(r) => r.read(buf),
+        let n = match self {
+            Self::OctetStream(r) => r.read(buf)?,
+            Self::Gzip(r) => r.read(buf)?,
+            Self::Bzip2(r) => r.read(buf)?,
+            Self::Xz(r) => r.read(buf)?,
+        };
+        if n == 0 && !buf.is_empty() {
+            std::io::copy(self.get_mut(), &mut std::io::sink())?;
         }
+        Ok(n)
     }
 
     // TODO other methods
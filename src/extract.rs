@@ -0,0 +1,1959 @@
+use std::collections::hash_map::Entry::Occupied;
+use std::collections::hash_map::Entry::Vacant;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::fs::File;
+use std::fs::FileTimes;
+use std::fs::Permissions;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::fchown;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use libc::c_int;
+use libc::makedev;
+use libc::AT_REMOVEDIR;
+use libc::O_CREAT;
+use libc::O_DIRECTORY;
+use libc::O_EXCL;
+use libc::O_NOFOLLOW;
+use libc::O_NONBLOCK;
+use libc::O_RDONLY;
+use libc::O_WRONLY;
+
+use crate::chmod_at;
+use crate::group_id;
+use crate::lchown_at;
+use crate::link_at;
+use crate::lstat_at;
+use crate::mkdir_at;
+use crate::mkfifo_at;
+use crate::mknod_at;
+use crate::open_at;
+use crate::path_to_c_string;
+use crate::remove_dir_all_at;
+use crate::set_file_flags;
+use crate::set_file_times_at;
+use crate::set_xattr;
+use crate::set_xattr_fd;
+use crate::symlink_at;
+use crate::unlink_at;
+use crate::user_id;
+use crate::xml;
+use crate::xml::ACL_ACCESS_XATTR;
+use crate::xml::ACL_DEFAULT_XATTR;
+use crate::Acl;
+use crate::ArchiveOptions;
+use crate::Entry;
+use crate::EntryError;
+use crate::ExtendedArchive;
+use crate::FileFlags;
+use crate::FileMode;
+use crate::FileType;
+use crate::HardLink;
+use crate::HardLinkMode;
+use crate::Hole;
+use crate::Overwrite;
+
+/// Extracts archive entries relative to the destination directory handle.
+pub(crate) struct Extractor {
+    dest_dir: OwnedFd,
+    #[cfg(not(target_os = "linux"))]
+    dest_dir_path: PathBuf,
+    options: ArchiveOptions,
+    // id -> path
+    file_paths: HashMap<u64, PathBuf>,
+    // (dev, inode) -> id
+    inodes: HashMap<(u64, u64), u64>,
+    hard_links: Vec<PendingHardLink>,
+    dirs: Vec<Dir>,
+    // (path, flags, id)
+    file_flags: Vec<(PathBuf, FileFlags, u64)>,
+    // directories that were not extracted because of the overwrite policy
+    skipped_dirs: Vec<PathBuf>,
+    // name -> local id
+    user_ids: HashMap<String, Option<u32>>,
+    group_ids: HashMap<String, Option<u32>>,
+    // metadata that was not restored
+    warnings: Vec<EntryError>,
+}
+
+impl Extractor {
+    pub(crate) fn new(dest_dir: &Path, options: ArchiveOptions) -> Result<Self, Error> {
+        let dest_dir_fd = File::open(dest_dir)?;
+        if !dest_dir_fd.metadata()?.is_dir() {
+            return Err(ErrorKind::NotADirectory.into());
+        }
+        Ok(Self {
+            dest_dir: dest_dir_fd.into(),
+            #[cfg(not(target_os = "linux"))]
+            dest_dir_path: dest_dir.to_path_buf(),
+            options,
+            file_paths: Default::default(),
+            inodes: Default::default(),
+            hard_links: Default::default(),
+            dirs: Default::default(),
+            file_flags: Default::default(),
+            skipped_dirs: Default::default(),
+            user_ids: Default::default(),
+            group_ids: Default::default(),
+            warnings: Default::default(),
+        })
+    }
+
+    pub(crate) fn extract<R: Read + Seek, X>(
+        &mut self,
+        entry: &mut Entry<'_, R, X>,
+    ) -> Result<(), Error> {
+        let path = self.entry_path(&entry.file().name)?;
+        let file_type = entry.file().kind;
+        self.file_paths.insert(entry.file().id, path.clone());
+        if file_type == FileType::Directory {
+            // directories can't be hard-linked
+            return self.create(entry, &path, file_type);
+        }
+        match self
+            .inodes
+            .entry((entry.file().deviceno, entry.file().inode))
+        {
+            Vacant(v) => {
+                if !matches!(file_type, FileType::HardLink(HardLink::Id(..))) {
+                    v.insert(entry.file().id);
+                }
+            }
+            Occupied(o) => {
+                // hard link
+                let id = *o.get();
+                return self.create(entry, &path, FileType::HardLink(HardLink::Id(id)));
+            }
+        }
+        self.create(entry, &path, file_type)
+    }
+
+    /// Extract the original file of the hard link to the hard link's path.
+    ///
+    /// Used when the original file itself is not extracted.
+    pub(crate) fn extract_hard_link_original<R: Read + Seek, X>(
+        &mut self,
+        original: &mut Entry<'_, R, X>,
+        hard_link_name: &Path,
+    ) -> Result<(), Error> {
+        let path = self.entry_path(hard_link_name)?;
+        let file = original.file();
+        self.file_paths.insert(file.id, path.clone());
+        self.inodes.insert((file.deviceno, file.inode), file.id);
+        self.create(original, &path, FileType::File)
+    }
+
+    /// Extract the entry as `name` relative to the destination directory.
+    ///
+    /// Unlike [`extract`](Self::extract) the archive path of the entry is not used and hard links
+    /// are not tracked, i.e. the entry is always extracted as a standalone file.
+    pub(crate) fn extract_as<R: Read + Seek, X>(
+        &mut self,
+        entry: &mut Entry<'_, R, X>,
+        name: &Path,
+    ) -> Result<(), Error> {
+        let file_type = match entry.file().kind {
+            FileType::HardLink(..) => FileType::File,
+            other => other,
+        };
+        self.create(entry, name, file_type)
+    }
+
+    /// Returns `true` if the file with the specified id has been extracted.
+    pub(crate) fn contains(&self, id: u64) -> bool {
+        self.file_paths.contains_key(&id)
+    }
+
+    fn create<R: Read + Seek, X>(
+        &mut self,
+        entry: &mut Entry<'_, R, X>,
+        path: &Path,
+        file_type: FileType,
+    ) -> Result<(), Error> {
+        if self.skipped_dirs.iter().any(|dir| path.starts_with(dir)) {
+            return Ok(());
+        }
+        let (dir, name) = self.open_parent(path)?;
+        let dir = dir.as_fd();
+        if !self.make_room(dir, &name, path, entry.file(), file_type)? {
+            if file_type == FileType::Directory {
+                // skip the contents as well
+                self.skipped_dirs.push(path.to_path_buf());
+            }
+            return Ok(());
+        }
+        let mode = self.permissions(entry.file().mode, file_type == FileType::Directory);
+        let id = entry.file().id;
+        if self.options.acls && entry.file().acl().is_some_and(|acl| acl.invalid) {
+            self.warn(
+                path,
+                id,
+                Error::new(ErrorKind::InvalidData, "invalid acl is ignored"),
+            );
+        }
+        let flags = entry.file().flags;
+        if self.options.file_flags
+            && !flags.is_empty()
+            && matches!(
+                file_type,
+                FileType::File | FileType::HardLink(HardLink::Original) | FileType::Directory
+            )
+        {
+            // apply the flags when all other files are written
+            self.file_flags
+                .push((path.to_path_buf(), flags, entry.file().id));
+        }
+        match file_type {
+            FileType::File | FileType::HardLink(HardLink::Original) => {
+                let flags = O_WRONLY | O_CREAT | O_EXCL | self.no_follow();
+                let mut out = File::from(open_at(dir, &name, flags, 0o600)?);
+                let holes = entry
+                    .file()
+                    .data()
+                    .map(|data| data.holes.clone())
+                    .unwrap_or_default();
+                if let Some(mut reader) = entry.reader()? {
+                    if holes.is_empty() {
+                        std::io::copy(&mut reader, &mut out)?;
+                    } else {
+                        copy_sparse(&mut reader, &mut out, &holes)?;
+                    }
+                }
+                let file = entry.file();
+                out.set_times(self.file_times(file.atime.0, file.mtime.0))?;
+                if self.options.preserve_owner {
+                    let (uid, gid) = self.owner(file);
+                    fchown(&out, Some(uid), Some(gid))?;
+                }
+                self.restore_xattrs(entry, Some(out.as_fd()), dir, &name, path)?;
+                out.set_permissions(Permissions::from_mode(mode))?;
+                // ACL mask overrides group permissions, i.e. this should be done after `chmod`
+                let access_acl = entry.file().acl().and_then(|acl| acl.access.as_ref());
+                self.restore_acl(out.as_fd(), ACL_ACCESS_XATTR, access_acl, path, id)?;
+            }
+            FileType::Directory => {
+                match mkdir_at(dir, &name, 0o700) {
+                    Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                    other => other?,
+                }
+                // Fails if the existing file is not a directory.
+                let flags = O_RDONLY | O_DIRECTORY | self.no_follow();
+                let out = File::from(open_at(dir, &name, flags, 0)?);
+                let file = entry.file();
+                if self.options.preserve_owner {
+                    let (uid, gid) = self.owner(file);
+                    fchown(&out, Some(uid), Some(gid))?;
+                }
+                let default_acl = file.acl().and_then(|acl| acl.default.as_ref());
+                self.restore_acl(out.as_fd(), ACL_DEFAULT_XATTR, default_acl, path, id)?;
+                // apply proper permissions and times later when we have written all other files
+                self.dirs.push(Dir {
+                    path: path.to_path_buf(),
+                    mode,
+                    id: file.id,
+                    acl: file.acl().and_then(|acl| acl.access.clone()),
+                    atime: file.atime.0,
+                    mtime: file.mtime.0,
+                });
+                self.restore_xattrs(entry, Some(out.as_fd()), dir, &name, path)?;
+            }
+            FileType::HardLink(HardLink::Id(id)) => {
+                // create hard links later because we might not have written
+                // the original files by now
+                let file = entry.file();
+                self.hard_links.push(PendingHardLink {
+                    original_id: id,
+                    path: path.to_path_buf(),
+                    id: file.id,
+                });
+            }
+            FileType::Symlink => {
+                let file = entry.file();
+                let target = file.link().ok_or(ErrorKind::InvalidData)?.target.clone();
+                symlink_at(&path_to_c_string(target)?, dir, &name)?;
+                self.preserve_metadata_at(dir, &name, path, entry)?;
+            }
+            FileType::Fifo => {
+                mkfifo_at(dir, &name, mode as _)?;
+                self.preserve_metadata_at(dir, &name, path, entry)?;
+                // The mode passed to `mkfifo` is affected by the process umask.
+                chmod_at(dir, &name, mode as _)?;
+            }
+            #[allow(unused_unsafe)]
+            FileType::CharacterSpecial | FileType::BlockSpecial => {
+                let file = entry.file();
+                let dev = file.device().ok_or(ErrorKind::InvalidData)?;
+                let dev = unsafe { makedev(dev.major as _, dev.minor as _) };
+                let kind = if file_type == FileType::CharacterSpecial {
+                    libc::S_IFCHR
+                } else {
+                    libc::S_IFBLK
+                };
+                mknod_at(dir, &name, kind | mode as libc::mode_t, dev as _)?;
+                self.preserve_metadata_at(dir, &name, path, entry)?;
+                chmod_at(dir, &name, mode as _)?;
+            }
+            FileType::Socket => {
+                #[cfg(target_os = "linux")]
+                mknod_at(dir, &name, libc::S_IFSOCK | mode as libc::mode_t, 0)?;
+                // `mknod` can't create sockets on this platform.
+                #[cfg(not(target_os = "linux"))]
+                std::os::unix::net::UnixDatagram::bind(self.dest_dir_path.join(path))?;
+                self.preserve_metadata_at(dir, &name, path, entry)?;
+                chmod_at(dir, &name, mode as _)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply [`Overwrite`] policy to the file that already exists at `path`.
+    ///
+    /// Returns `false` if the entry should not be extracted.
+    fn make_room<X>(
+        &self,
+        dir: BorrowedFd,
+        name: &CStr,
+        path: &Path,
+        file: &xml::File<X>,
+        file_type: FileType,
+    ) -> Result<bool, Error> {
+        let stat = match lstat_at(dir, name) {
+            Ok(stat) => stat,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(true),
+            Err(e) => return Err(e),
+        };
+        let is_dir = stat.st_mode & libc::S_IFMT == libc::S_IFDIR;
+        if is_dir && file_type == FileType::Directory {
+            // merge directories
+            return Ok(true);
+        }
+        match self.options.overwrite {
+            Overwrite::Error => Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("file exists: {}", path.display()),
+            )),
+            Overwrite::Skip => Ok(false),
+            Overwrite::KeepNewer if modified_time(&stat) > file.mtime.0 => Ok(false),
+            Overwrite::KeepNewer | Overwrite::Replace => {
+                if !is_dir {
+                    unlink_at(dir, name, 0)?;
+                } else if self.options.recursive_unlink {
+                    remove_dir_all_at(dir, name)?;
+                } else {
+                    match unlink_at(dir, name, AT_REMOVEDIR) {
+                        Err(e) if e.kind() == ErrorKind::DirectoryNotEmpty => {
+                            return Err(Error::new(
+                                e.kind(),
+                                format!("can't replace non-empty directory: {}", path.display()),
+                            ));
+                        }
+                        other => other?,
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Create hard links and set directory permissions.
+    ///
+    /// Hard link copies are extracted from `archive`.
+    /// The errors are passed to `on_error` that decides whether to continue or not.
+    /// Returns the warnings collected during the extraction.
+    pub(crate) fn finish<R, X, F>(
+        mut self,
+        archive: &mut ExtendedArchive<R, X>,
+        mut on_error: F,
+    ) -> Result<Vec<EntryError>, Error>
+    where
+        R: Read + Seek,
+        F: FnMut(EntryError) -> Result<(), Error>,
+    {
+        for hard_link in std::mem::take(&mut self.hard_links).into_iter() {
+            let result = match self.options.hard_links {
+                HardLinkMode::Link => self.link(&hard_link),
+                HardLinkMode::Copy => self.copy(archive, &hard_link),
+                HardLinkMode::LinkOrCopy => match self.link(&hard_link) {
+                    Err(e) if matches!(e.raw_os_error(), Some(libc::EXDEV | libc::EPERM)) => {
+                        self.copy(archive, &hard_link)
+                    }
+                    other => other,
+                },
+            };
+            if let Err(error) = result {
+                on_error(EntryError {
+                    path: hard_link.path,
+                    id: hard_link.id,
+                    error,
+                })?;
+            }
+        }
+        let mut dirs = std::mem::take(&mut self.dirs);
+        dirs.sort_unstable_by(|a, b| b.path.cmp(&a.path));
+        for dir in dirs.into_iter() {
+            let result = self.open_dir(&dir.path).and_then(|fd| {
+                let file = File::from(fd.try_clone()?);
+                file.set_permissions(Permissions::from_mode(dir.mode))?;
+                self.restore_acl(
+                    fd.as_fd(),
+                    ACL_ACCESS_XATTR,
+                    dir.acl.as_ref(),
+                    &dir.path,
+                    dir.id,
+                )?;
+                file.set_times(self.file_times(dir.atime, dir.mtime))
+            });
+            if let Err(error) = result {
+                on_error(EntryError {
+                    path: dir.path,
+                    id: dir.id,
+                    error,
+                })?;
+            }
+        }
+        for (path, flags, id) in std::mem::take(&mut self.file_flags).into_iter() {
+            let result = self.open_parent(&path).and_then(|(dir, name)| {
+                let flags_to_open = O_RDONLY | O_NONBLOCK | self.no_follow();
+                let file = open_at(dir.as_fd(), &name, flags_to_open, 0)?;
+                set_file_flags(file.as_fd(), flags)
+            });
+            if let Err(error) = result {
+                on_error(EntryError { path, id, error })?;
+            }
+        }
+        Ok(self.warnings)
+    }
+
+    fn link(&self, hard_link: &PendingHardLink) -> Result<(), Error> {
+        let original = self.original_path(hard_link)?;
+        let (original_dir, original_name) = self.open_parent(original)?;
+        let (dir, name) = self.open_parent(&hard_link.path)?;
+        link_at(original_dir.as_fd(), &original_name, dir.as_fd(), &name)
+    }
+
+    /// Copy the original file of the hard link.
+    ///
+    /// The contents and the metadata are read from the archive rather than from the extracted
+    /// original, i.e. the copy doesn't depend on the permissions of the original, doesn't change
+    /// its access time and preserves the holes.
+    fn copy<R: Read + Seek, X>(
+        &mut self,
+        archive: &mut ExtendedArchive<R, X>,
+        hard_link: &PendingHardLink,
+    ) -> Result<(), Error> {
+        let original_id = hard_link.original_id;
+        let mut original = archive.entry_by_id(original_id).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("hard link to unknown file id {original_id}"),
+            )
+        })?;
+        if !matches!(
+            original.file().kind,
+            FileType::File | FileType::HardLink(HardLink::Original)
+        ) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "hard link to non-regular file",
+            ));
+        }
+        self.create(&mut original, &hard_link.path, FileType::File)
+    }
+
+    fn original_path(&self, hard_link: &PendingHardLink) -> Result<&Path, Error> {
+        let original_id = hard_link.original_id;
+        self.file_paths
+            .get(&original_id)
+            .map(|path| path.as_path())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("hard link to unknown file id {original_id}"),
+                )
+            })
+    }
+
+    /// Get the path of the entry relative to the destination directory.
+    ///
+    /// Unsafe paths are sanitized or rejected if [`sanitize_paths`](ArchiveOptions::sanitize_paths)
+    /// is enabled.
+    fn entry_path(&self, name: &Path) -> Result<PathBuf, Error> {
+        if !self.options.sanitize_paths {
+            return Ok(name.to_path_buf());
+        }
+        let mut path = PathBuf::new();
+        for component in name.components() {
+            match component {
+                Component::Normal(component) => path.push(component),
+                // strip leading "/" and "."
+                Component::RootDir | Component::Prefix(..) | Component::CurDir => {}
+                Component::ParentDir => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("unsafe path: {}", name.display()),
+                    ));
+                }
+            }
+        }
+        if path.as_os_str().is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("empty path: {}", name.display()),
+            ));
+        }
+        Ok(path)
+    }
+
+    /// Open parent directory of `path` and return its handle and the file name.
+    fn open_parent(&self, path: &Path) -> Result<(OwnedFd, CString), Error> {
+        let name = path.file_name().ok_or(ErrorKind::InvalidData)?;
+        let dir = self.open_dir(path.parent().unwrap_or(Path::new("")))?;
+        Ok((dir, CString::new(name.as_bytes())?))
+    }
+
+    /// Open directory relative to the destination directory.
+    ///
+    /// The directory is opened component by component, missing directories are created with
+    /// `0o755` permissions. When paths are sanitized symbolic links are never followed.
+    fn open_dir(&self, path: &Path) -> Result<OwnedFd, Error> {
+        let flags = O_RDONLY | O_DIRECTORY | self.no_follow();
+        let mut dir = self.dest_dir.try_clone()?;
+        for component in path.components() {
+            let name = match component {
+                Component::RootDir => {
+                    dir = open_at(dir.as_fd(), c"/", flags, 0)?;
+                    continue;
+                }
+                Component::Prefix(..) | Component::CurDir => continue,
+                Component::Normal(..) | Component::ParentDir => {
+                    CString::new(component.as_os_str().as_bytes())?
+                }
+            };
+            dir = match open_at(dir.as_fd(), &name, flags, 0) {
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    match mkdir_at(dir.as_fd(), &name, 0o755) {
+                        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                        other => other?,
+                    }
+                    open_at(dir.as_fd(), &name, flags, 0)?
+                }
+                other => other?,
+            };
+        }
+        Ok(dir)
+    }
+
+    /// Preserve metadata of the file that can't be opened (symlinks, devices etc.).
+    fn preserve_metadata_at<R: Read + Seek, X>(
+        &mut self,
+        dir: BorrowedFd,
+        name: &CStr,
+        path: &Path,
+        entry: &mut Entry<'_, R, X>,
+    ) -> Result<(), Error> {
+        let file = entry.file();
+        let atime = self.options.preserve_atime.then_some(file.atime.0);
+        let mtime = self.options.preserve_mtime.then_some(file.mtime.0);
+        if atime.is_some() || mtime.is_some() {
+            set_file_times_at(dir, name, atime, mtime)?;
+        }
+        if self.options.preserve_owner {
+            let (uid, gid) = self.owner(file);
+            lchown_at(dir, name, uid, gid)?;
+        }
+        self.restore_xattrs(entry, None, dir, name, path)
+    }
+
+    /// Restore extended attributes either via file descriptor `fd` or via the path if the file
+    /// can't be opened.
+    ///
+    /// The attributes that the file system or the file type doesn't support (e.g. `user.*`
+    /// attributes of symlinks on Linux) are skipped with a warning. Privileged attributes that
+    /// can't be set are skipped silently.
+    fn restore_xattrs<R: Read + Seek, X>(
+        &mut self,
+        entry: &mut Entry<'_, R, X>,
+        fd: Option<BorrowedFd>,
+        dir: BorrowedFd,
+        name: &CStr,
+        path: &Path,
+    ) -> Result<(), Error> {
+        if !self.options.xattrs {
+            return Ok(());
+        }
+        let xattr_names: Vec<String> = entry
+            .file()
+            .xattrs()
+            .iter()
+            .map(|xattr| xattr.name.clone())
+            .filter(|xattr_name| {
+                xml::is_portable_xattr(xattr_name) && self.options.xattr_filter.matches(xattr_name)
+            })
+            .collect();
+        if xattr_names.is_empty() {
+            return Ok(());
+        }
+        let xattr_path = match fd {
+            Some(..) => None,
+            None => Some(self.xattr_path(dir, name, path)?),
+        };
+        for xattr_name in xattr_names.into_iter() {
+            let Some(value) = entry.read_xattr(&xattr_name)? else {
+                continue;
+            };
+            let c_name = CString::new(xattr_name.as_bytes())?;
+            let result = match (fd, xattr_path.as_ref()) {
+                (Some(fd), _) => set_xattr_fd(fd, &c_name, &value),
+                (None, Some(xattr_path)) => set_xattr(xattr_path, &c_name, &value),
+                (None, None) => Err(ErrorKind::InvalidInput.into()),
+            };
+            match result {
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::PermissionDenied | ErrorKind::Unsupported
+                    ) =>
+                {
+                    if !is_privileged_xattr(&xattr_name) {
+                        let id = entry.file().id;
+                        let error =
+                            Error::new(e.kind(), format!("failed to set {xattr_name}: {e}"));
+                        self.warn(path, id, error);
+                    }
+                }
+                other => other?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Set ACL via extended attribute `xattr_name` if enabled.
+    ///
+    /// The ACL is skipped with a warning if it's not supported by the platform or the file system
+    /// or if it refers to unknown users or groups.
+    fn restore_acl(
+        &mut self,
+        fd: BorrowedFd,
+        xattr_name: &CStr,
+        acl: Option<&Acl>,
+        path: &Path,
+        id: u64,
+    ) -> Result<(), Error> {
+        let Some(acl) = acl.filter(|_| self.options.acls) else {
+            return Ok(());
+        };
+        let result = if cfg!(target_os = "linux") {
+            acl.to_xattr()
+                .and_then(|value| set_xattr_fd(fd, xattr_name, &value))
+        } else {
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "POSIX ACLs are not supported on this platform",
+            ))
+        };
+        match result {
+            Err(e) if matches!(e.kind(), ErrorKind::Unsupported | ErrorKind::NotFound) => {
+                self.warn(path, id, e);
+                Ok(())
+            }
+            other => other,
+        }
+    }
+
+    /// Record the problem that doesn't prevent the entry from being extracted.
+    fn warn(&mut self, path: &Path, id: u64, error: Error) {
+        self.warnings.push(EntryError {
+            path: path.to_path_buf(),
+            id,
+            error,
+        });
+    }
+
+    /// Get the path of the file that can't be opened to set its extended attributes.
+    #[cfg(target_os = "linux")]
+    fn xattr_path(&self, dir: BorrowedFd, name: &CStr, _path: &Path) -> Result<CString, Error> {
+        let mut xattr_path = format!("/proc/self/fd/{}/", dir.as_raw_fd()).into_bytes();
+        xattr_path.extend_from_slice(name.to_bytes());
+        Ok(CString::new(xattr_path)?)
+    }
+
+    /// Get the path of the file that can't be opened to set its extended attributes.
+    #[cfg(not(target_os = "linux"))]
+    fn xattr_path(&self, _dir: BorrowedFd, _name: &CStr, path: &Path) -> Result<CString, Error> {
+        path_to_c_string(self.dest_dir_path.join(path))
+    }
+
+    /// Get the owner of the extracted file.
+    ///
+    /// The owner is remapped via [`owner_map`](ArchiveOptions::owner_map) first.
+    /// Then user and group names are mapped to local ids unless
+    /// [`numeric_owner`](ArchiveOptions::numeric_owner) is enabled.
+    /// Archived ids are used if the names are missing or unknown.
+    fn owner<X>(&mut self, file: &xml::File<X>) -> (u32, u32) {
+        let owner_map = &self.options.owner_map;
+        let (uid, user) = owner_map.user_of(file.uid, file.user.as_deref());
+        let (gid, group) = owner_map.group_of(file.gid, file.group.as_deref());
+        if self.options.numeric_owner {
+            return (uid, gid);
+        }
+        let uid = user
+            .and_then(|name| {
+                *self
+                    .user_ids
+                    .entry(name)
+                    .or_insert_with_key(|name| user_id(name).unwrap_or_default())
+            })
+            .unwrap_or(uid);
+        let gid = group
+            .and_then(|name| {
+                *self
+                    .group_ids
+                    .entry(name)
+                    .or_insert_with_key(|name| group_id(name).unwrap_or_default())
+            })
+            .unwrap_or(gid);
+        (uid, gid)
+    }
+
+    /// Apply permission policy to the archived file mode.
+    fn permissions(&self, mode: FileMode, is_dir: bool) -> u32 {
+        let mut mode = match (self.options.same_permissions, is_dir) {
+            (true, _) => mode.into_inner(),
+            (false, true) => 0o777,
+            (false, false) => 0o666,
+        };
+        if !self.options.keep_setuid {
+            // setuid and setgid; setgid on directories only makes new files inherit the group
+            mode &= if is_dir { !0o4000 } else { !0o6000 };
+        }
+        mode & !self.options.umask & 0o7777
+    }
+
+    /// Get the times that need to be preserved.
+    fn file_times(&self, atime: SystemTime, mtime: SystemTime) -> FileTimes {
+        let mut times = FileTimes::new();
+        if self.options.preserve_atime {
+            times = times.set_accessed(atime);
+        }
+        if self.options.preserve_mtime {
+            times = times.set_modified(mtime);
+        }
+        times
+    }
+
+    fn no_follow(&self) -> c_int {
+        if self.options.sanitize_paths {
+            O_NOFOLLOW
+        } else {
+            0
+        }
+    }
+}
+
+struct PendingHardLink {
+    original_id: u64,
+    path: PathBuf,
+    id: u64,
+}
+
+struct Dir {
+    path: PathBuf,
+    mode: u32,
+    id: u64,
+    // access ACL
+    acl: Option<Acl>,
+    atime: SystemTime,
+    mtime: SystemTime,
+}
+
+fn is_privileged_xattr(name: &str) -> bool {
+    name.starts_with("trusted.") || name.starts_with("security.")
+}
+
+/// Copy file contents skipping the zero blocks that lie within the `holes`.
+///
+/// The blocks are skipped by seeking, i.e. they are not allocated on disk.
+fn copy_sparse<R: Read>(reader: &mut R, out: &mut File, holes: &[Hole]) -> Result<(), Error> {
+    let mut buf = vec![0_u8; SPARSE_BLOCK_LEN];
+    let mut pos = 0;
+    for hole in holes.iter() {
+        if hole.offset < pos {
+            return Err(Error::new(ErrorKind::InvalidData, "overlapping holes"));
+        }
+        std::io::copy(&mut reader.take(hole.offset - pos), out)?;
+        let mut remaining = hole.length;
+        while remaining != 0 {
+            let n = remaining.min(buf.len() as u64) as usize;
+            reader.read_exact(&mut buf[..n])?;
+            if buf[..n].iter().all(|b| *b == 0) {
+                out.seek(SeekFrom::Current(n as i64))?;
+            } else {
+                // not a hole after all
+                out.write_all(&buf[..n])?;
+            }
+            remaining -= n as u64;
+        }
+        pos = hole.offset + hole.length;
+    }
+    std::io::copy(reader, out)?;
+    // extend the file if it ends with a hole
+    let len = out.stream_position()?;
+    out.set_len(len)?;
+    Ok(())
+}
+
+fn modified_time(stat: &libc::stat) -> SystemTime {
+    let nanos = Duration::from_nanos(stat.st_mtime_nsec as u64);
+    if stat.st_mtime >= 0 {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(stat.st_mtime as u64) + nanos
+    } else {
+        SystemTime::UNIX_EPOCH - Duration::from_secs(stat.st_mtime.unsigned_abs()) + nanos
+    }
+}
+
+const SPARSE_BLOCK_LEN: usize = 4096;
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+    use std::os::unix::fs::MetadataExt;
+
+    use arbtest::arbtest;
+    use random_dir::list_dir_all;
+    use random_dir::DirBuilder;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::get_file_flags;
+    use crate::get_xattr;
+    use crate::group_name;
+    use crate::user_name;
+    use crate::AclEntry;
+    use crate::AclTag;
+    use crate::Archive;
+    use crate::BuilderOptions;
+    use crate::ChecksumAlgo;
+    use crate::Compression;
+    use crate::FileAcl;
+    use crate::NoSigner;
+    use crate::OwnerMap;
+    use crate::Timestamp;
+    use crate::UnsignedBuilder;
+    use crate::XattrFilter;
+
+    #[test]
+    fn parent_dir_is_rejected() {
+        let workdir = TempDir::new().unwrap();
+        let dest_dir = workdir.path().join("dest");
+        std::fs::create_dir(&dest_dir).unwrap();
+        let archive = hostile_archive(workdir.path(), &[("file", "../evil")], Default::default());
+        assert!(archive.extract(&dest_dir).is_err());
+        assert!(!workdir.path().join("evil").exists());
+    }
+
+    #[test]
+    fn root_dir_is_stripped() {
+        let workdir = TempDir::new().unwrap();
+        let dest_dir = workdir.path().join("dest");
+        std::fs::create_dir(&dest_dir).unwrap();
+        let archive = hostile_archive(workdir.path(), &[("file", "/evil")], Default::default());
+        archive.extract(&dest_dir).unwrap();
+        assert!(dest_dir.join("evil").exists());
+    }
+
+    #[test]
+    fn symlinks_are_not_followed() {
+        let workdir = TempDir::new().unwrap();
+        let dest_dir = workdir.path().join("dest");
+        let outside_dir = workdir.path().join("outside");
+        std::fs::create_dir(&dest_dir).unwrap();
+        std::fs::create_dir(&outside_dir).unwrap();
+        let archive = hostile_archive(
+            workdir.path(),
+            &[("symlink", "link"), ("file", "link/evil")],
+            Default::default(),
+        );
+        assert!(archive.extract(&dest_dir).is_err());
+        assert!(!outside_dir.join("evil").exists());
+        assert!(dest_dir
+            .join("link")
+            .symlink_metadata()
+            .unwrap()
+            .is_symlink());
+    }
+
+    #[test]
+    fn symlinks_are_followed_without_sanitizing() {
+        let workdir = TempDir::new().unwrap();
+        let dest_dir = workdir.path().join("dest");
+        let outside_dir = workdir.path().join("outside");
+        std::fs::create_dir(&dest_dir).unwrap();
+        std::fs::create_dir(&outside_dir).unwrap();
+        let archive = hostile_archive(
+            workdir.path(),
+            &[("symlink", "link"), ("file", "link/evil")],
+            ArchiveOptions::new().sanitize_paths(false),
+        );
+        archive.extract(&dest_dir).unwrap();
+        assert!(outside_dir.join("evil").exists());
+    }
+
+    #[test]
+    fn extract_filtered() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir_all(src_dir.join("a").join("b")).unwrap();
+        std::fs::write(src_dir.join("a").join("b").join("c"), "c").unwrap();
+        std::fs::write(src_dir.join("a").join("d"), "d").unwrap();
+        std::fs::write(src_dir.join("x"), "x").unwrap();
+        std::fs::hard_link(src_dir.join("x"), src_dir.join("y")).unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        let hard_link = archive
+            .files()
+            .iter()
+            .find(|file| matches!(file.kind, FileType::HardLink(HardLink::Id(..))))
+            .unwrap()
+            .name
+            .clone();
+        let original = if hard_link == Path::new("x") {
+            "y"
+        } else {
+            "x"
+        };
+        let filter = crate::PathFilter::new()
+            .include("a/b/c")
+            .unwrap()
+            .include_path(&hard_link);
+        let dest_dir = workdir.path().join("dest");
+        std::fs::create_dir(&dest_dir).unwrap();
+        archive
+            .extract_filtered(&dest_dir, |file| filter.matches(&file.name))
+            .unwrap();
+        assert_eq!(
+            "c",
+            std::fs::read_to_string(dest_dir.join("a/b/c")).unwrap()
+        );
+        assert_eq!(
+            "x",
+            std::fs::read_to_string(dest_dir.join(&hard_link)).unwrap()
+        );
+        assert!(!dest_dir.join("a/d").exists());
+        assert!(!dest_dir.join(original).exists());
+        // missing parent directories are not writable by others
+        let mode = std::fs::metadata(dest_dir.join("a/b")).unwrap().mode();
+        assert_eq!(0, mode & 0o7777 & !0o755);
+    }
+
+    #[test]
+    fn overwrite() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        let outside_dir = workdir.path().join("outside");
+        std::fs::create_dir_all(src_dir.join("d")).unwrap();
+        std::fs::create_dir_all(&outside_dir).unwrap();
+        std::fs::write(src_dir.join("d").join("g"), "new").unwrap();
+        std::fs::write(src_dir.join("f"), "new").unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        set_modified(&src_dir.join("f"), mtime);
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        let extract = |overwrite: Overwrite, existing_mtime: SystemTime| {
+            let dest_dir = workdir.path().join("dest");
+            std::fs::remove_dir_all(&dest_dir).ok();
+            std::fs::create_dir_all(&dest_dir).unwrap();
+            std::fs::write(dest_dir.join("f"), "old").unwrap();
+            set_modified(&dest_dir.join("f"), existing_mtime);
+            symlink(&outside_dir, dest_dir.join("d")).unwrap();
+            let archive = Archive::with_options(
+                File::open(&xar_path).unwrap(),
+                ArchiveOptions::new().overwrite(overwrite),
+            )
+            .unwrap();
+            let result = archive.extract(&dest_dir);
+            let contents = std::fs::read_to_string(dest_dir.join("f")).unwrap();
+            (result, contents, dest_dir)
+        };
+        let newer = mtime + Duration::from_secs(100);
+        let older = mtime - Duration::from_secs(100);
+        let (result, contents, _) = extract(Overwrite::Error, older);
+        assert_eq!(ErrorKind::AlreadyExists, result.unwrap_err().kind());
+        assert_eq!("old", contents);
+        let (result, contents, dest_dir) = extract(Overwrite::Skip, older);
+        result.unwrap();
+        assert_eq!("old", contents);
+        assert!(dest_dir.join("d").symlink_metadata().unwrap().is_symlink());
+        assert!(!outside_dir.join("g").exists());
+        let (result, contents, _) = extract(Overwrite::KeepNewer, newer);
+        result.unwrap();
+        assert_eq!("old", contents);
+        let (result, contents, _) = extract(Overwrite::KeepNewer, older);
+        result.unwrap();
+        assert_eq!("new", contents);
+        let (result, contents, dest_dir) = extract(Overwrite::Replace, newer);
+        result.unwrap();
+        assert_eq!("new", contents);
+        assert!(dest_dir.join("d").symlink_metadata().unwrap().is_dir());
+        assert_eq!(
+            "new",
+            std::fs::read_to_string(dest_dir.join("d").join("g")).unwrap()
+        );
+        assert!(!outside_dir.join("g").exists());
+    }
+
+    #[test]
+    fn overwrite_non_empty_dir() {
+        let workdir = TempDir::new().unwrap();
+        let outside_dir = workdir.path().join("outside");
+        std::fs::create_dir_all(&outside_dir).unwrap();
+        std::fs::write(outside_dir.join("g"), "outside").unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        for name in ["e", "f"] {
+            builder
+                .append_file(name, &Default::default(), Compression::Gzip, &b"new"[..])
+                .unwrap();
+        }
+        builder
+            .append_symlink("s", &Default::default(), "f")
+            .unwrap();
+        builder.finish().unwrap();
+        let dest_dir = workdir.path().join("dest");
+        let extract = |recursive_unlink: bool| {
+            std::fs::remove_dir_all(&dest_dir).ok();
+            std::fs::create_dir_all(dest_dir.join("e")).unwrap();
+            for name in ["f", "s"] {
+                std::fs::create_dir_all(dest_dir.join(name).join("d")).unwrap();
+                std::fs::write(dest_dir.join(name).join("d").join("g"), "old").unwrap();
+                symlink(&outside_dir, dest_dir.join(name).join("outside")).unwrap();
+            }
+            let archive = Archive::with_options(
+                File::open(&xar_path).unwrap(),
+                ArchiveOptions::new()
+                    .overwrite(Overwrite::Replace)
+                    .recursive_unlink(recursive_unlink),
+            )
+            .unwrap();
+            archive.extract_best_effort(&dest_dir, |_| true).unwrap()
+        };
+        // Only empty directories are replaced by default.
+        let report = extract(false);
+        let mut errors: Vec<_> = report
+            .errors
+            .iter()
+            .map(|error| (error.path.clone(), error.kind()))
+            .collect();
+        errors.sort();
+        assert_eq!(
+            vec![
+                (PathBuf::from("f"), ErrorKind::DirectoryNotEmpty),
+                (PathBuf::from("s"), ErrorKind::DirectoryNotEmpty)
+            ],
+            errors
+        );
+        assert_eq!("new", std::fs::read_to_string(dest_dir.join("e")).unwrap());
+        for name in ["f", "s"] {
+            assert_eq!(
+                "old",
+                std::fs::read_to_string(dest_dir.join(name).join("d").join("g")).unwrap()
+            );
+        }
+        // Non-empty directories are removed with their contents on request.
+        let report = extract(true);
+        assert!(report.is_ok(), "{:?}", report.errors);
+        assert_eq!("new", std::fs::read_to_string(dest_dir.join("f")).unwrap());
+        assert!(dest_dir.join("s").symlink_metadata().unwrap().is_symlink());
+        assert_eq!(
+            "outside",
+            std::fs::read_to_string(outside_dir.join("g")).unwrap()
+        );
+    }
+
+    #[test]
+    fn extract_best_effort() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir_all(&src_dir).unwrap();
+        std::fs::write(src_dir.join("file"), "contents").unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        // the heap starts with the checksum of the table of contents
+        let mut offset = ChecksumAlgo::default().hash_len() as u64;
+        for (i, name) in ["a", "corrupted", "dangling", "b"].into_iter().enumerate() {
+            let (mut file, mut contents) = xml::File::<()>::new(
+                i as u64 + 1,
+                "",
+                src_dir.join("file"),
+                name.into(),
+                Compression::None,
+                ChecksumAlgo::Sha256,
+                offset,
+                None,
+            )
+            .unwrap();
+            offset += contents.len() as u64;
+            file.inode = i as u64 + 1;
+            match name {
+                "corrupted" => contents = b"CONTENTS".to_vec(),
+                "dangling" => file.kind = FileType::HardLink(HardLink::Id(1000)),
+                _ => {}
+            }
+            builder.append_raw(file, contents).unwrap();
+        }
+        builder.finish().unwrap();
+        let dest_dir = workdir.path().join("dest");
+        std::fs::create_dir(&dest_dir).unwrap();
+        let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        let report = archive.extract_best_effort(&dest_dir, |_| true).unwrap();
+        assert!(!report.is_ok());
+        let errors: Vec<_> = report
+            .errors
+            .iter()
+            .map(|e| (e.path.as_path(), e.id, e.kind()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Path::new("corrupted"), 2, ErrorKind::InvalidData),
+                (Path::new("dangling"), 3, ErrorKind::InvalidData)
+            ],
+            errors
+        );
+        for name in ["a", "b"] {
+            assert_eq!(
+                "contents",
+                std::fs::read_to_string(dest_dir.join(name)).unwrap()
+            );
+        }
+        let dest_dir = workdir.path().join("dest2");
+        std::fs::create_dir(&dest_dir).unwrap();
+        let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        assert!(archive.extract(&dest_dir).is_err());
+        assert!(!dest_dir.join("b").exists());
+    }
+
+    #[test]
+    fn xattrs() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir_all(src_dir.join("dir")).unwrap();
+        std::fs::write(src_dir.join("file"), "contents").unwrap();
+        let set = |path: &Path, name: &CStr, value: &[u8]| {
+            set_xattr(&path_to_c_string(path.to_path_buf()).unwrap(), name, value)
+        };
+        match set(&src_dir.join("file"), c"user.a", b"a") {
+            Err(e) if e.kind() == ErrorKind::Unsupported => return,
+            other => other.unwrap(),
+        }
+        set(&src_dir.join("file"), c"user.b", b"b").unwrap();
+        set(&src_dir.join("file"), c"user.quarantine", b"q").unwrap();
+        set(&src_dir.join("dir"), c"user.c", b"c").unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = BuilderOptions::new()
+            .xattr_filter(XattrFilter::new().exclude("user.quarantine").unwrap())
+            .create(File::create(&xar_path).unwrap(), None::<NoSigner>);
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        let mut archive = Archive::with_options(
+            File::open(&xar_path).unwrap(),
+            ArchiveOptions::new().xattr_filter(XattrFilter::new().exclude("user.b").unwrap()),
+        )
+        .unwrap();
+        let mut entry = archive.entry_by_path("file").unwrap();
+        let mut names: Vec<_> = entry
+            .file()
+            .xattrs()
+            .iter()
+            .map(|xattr| xattr.name.as_str())
+            .collect();
+        names.sort();
+        assert_eq!(vec!["user.a", "user.b"], names);
+        assert_eq!(Some(b"b".to_vec()), entry.read_xattr("user.b").unwrap());
+        assert_eq!(None, entry.read_xattr("user.quarantine").unwrap());
+        let mut buf = String::new();
+        entry
+            .reader()
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut buf)
+            .unwrap();
+        assert_eq!("contents", buf);
+        let dest_dir = workdir.path().join("dest");
+        std::fs::create_dir(&dest_dir).unwrap();
+        archive.extract(&dest_dir).unwrap();
+        let get = |path: &Path, name: &CStr| {
+            crate::get_xattr(&path_to_c_string(path.to_path_buf()).unwrap(), name).ok()
+        };
+        assert_eq!(Some(b"a".to_vec()), get(&dest_dir.join("file"), c"user.a"));
+        assert_eq!(None, get(&dest_dir.join("file"), c"user.b"));
+        assert_eq!(None, get(&dest_dir.join("file"), c"user.quarantine"));
+        assert_eq!(Some(b"c".to_vec()), get(&dest_dir.join("dir"), c"user.c"));
+        assert_eq!(
+            "contents",
+            std::fs::read_to_string(dest_dir.join("file")).unwrap()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn symlink_user_xattrs() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir(&src_dir).unwrap();
+        std::fs::write(src_dir.join("file"), "contents").unwrap();
+        match set_xattr(
+            &path_to_c_string(src_dir.join("file")).unwrap(),
+            c"user.a",
+            b"a",
+        ) {
+            Err(e) if e.kind() == ErrorKind::Unsupported => return,
+            other => other.unwrap(),
+        }
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        // Linux doesn't allow `user.*` attributes on symlinks, i.e. reuse the file's attributes
+        // as if the archive was created on another platform.
+        let ea = builder.files()[0].xattrs().to_vec();
+        builder
+            .append_symlink("symlink", &Default::default(), "file")
+            .unwrap()
+            .ea = ea;
+        builder.finish().unwrap();
+        let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        let dest_dir = workdir.path().join("dest");
+        std::fs::create_dir(&dest_dir).unwrap();
+        let report = archive.extract_best_effort(&dest_dir, |_| true).unwrap();
+        assert!(report.is_ok(), "{:?}", report.errors);
+        let warnings: Vec<_> = report
+            .warnings
+            .iter()
+            .map(|warning| warning.path.clone())
+            .collect();
+        assert_eq!(vec![PathBuf::from("symlink")], warnings);
+        assert_eq!(
+            Path::new("file"),
+            std::fs::read_link(dest_dir.join("symlink")).unwrap()
+        );
+        assert_eq!(
+            b"a".to_vec(),
+            crate::get_xattr(&path_to_c_string(dest_dir.join("file")).unwrap(), c"user.a").unwrap()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn acls() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir_all(src_dir.join("dir")).unwrap();
+        std::fs::write(src_dir.join("file"), "contents").unwrap();
+        let access: Acl = "user::rw-,user:1234:r--,group::r--,mask::r--,other::---"
+            .parse()
+            .unwrap();
+        let default: Acl = "user::rwx,group::r-x,group:1234:rwx,mask::rwx,other::---"
+            .parse()
+            .unwrap();
+        let c_path = |path: PathBuf| path_to_c_string(path).unwrap();
+        match set_xattr(
+            &c_path(src_dir.join("file")),
+            ACL_ACCESS_XATTR,
+            &access.to_xattr().unwrap(),
+        ) {
+            Err(e) if e.kind() == ErrorKind::Unsupported => return,
+            other => other.unwrap(),
+        }
+        set_xattr(
+            &c_path(src_dir.join("dir")),
+            ACL_DEFAULT_XATTR,
+            &default.to_xattr().unwrap(),
+        )
+        .unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        let get = |path: PathBuf, name: &CStr| {
+            crate::get_xattr(&c_path(path), name)
+                .ok()
+                .map(|value| Acl::from_xattr(&value).unwrap())
+        };
+        for acls in [false, true] {
+            let archive = Archive::with_options(
+                File::open(&xar_path).unwrap(),
+                ArchiveOptions::new().acls(acls),
+            )
+            .unwrap();
+            let file = &archive.files()[archive.find("file").unwrap()];
+            assert_eq!(Some(&access), file.acl().unwrap().access.as_ref());
+            assert_eq!(None, file.acl().unwrap().default.as_ref());
+            let dir = &archive.files()[archive.find("dir").unwrap()];
+            assert_eq!(None, dir.acl().unwrap().access.as_ref());
+            assert_eq!(Some(&default), dir.acl().unwrap().default.as_ref());
+            let dest_dir = workdir.path().join(format!("dest-{acls}"));
+            std::fs::create_dir(&dest_dir).unwrap();
+            archive.extract(&dest_dir).unwrap();
+            let expected = |acl: &Acl| acls.then(|| acl.clone());
+            assert_eq!(
+                expected(&access),
+                get(dest_dir.join("file"), ACL_ACCESS_XATTR)
+            );
+            assert_eq!(
+                expected(&default),
+                get(dest_dir.join("dir"), ACL_DEFAULT_XATTR)
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn acls_non_fatal() {
+        let workdir = TempDir::new().unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let named: Acl = "user::rw-,user:no-such-user-zar:r--,group::r--,mask::r--,other::---"
+            .parse()
+            .unwrap();
+        // serialized as "user:a:b:r--" that can't be parsed
+        let invalid = Acl::new(vec![AclEntry {
+            tag: AclTag::UserName("a:b".into()),
+            perms: 4,
+        }]);
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        for (name, acl) in [("named", named.clone()), ("invalid", invalid)] {
+            let file = builder
+                .append_file(
+                    name,
+                    &Default::default(),
+                    Compression::None,
+                    &b"contents"[..],
+                )
+                .unwrap();
+            file.acl = Some(FileAcl {
+                access: Some(acl),
+                default: None,
+                invalid: false,
+            });
+        }
+        builder.finish().unwrap();
+        let archive = Archive::with_options(
+            File::open(&xar_path).unwrap(),
+            ArchiveOptions::new().acls(true),
+        )
+        .unwrap();
+        let file = &archive.files()[archive.find("named").unwrap()];
+        assert_eq!(Some(&named), file.acl().unwrap().access.as_ref());
+        let file = &archive.files()[archive.find("invalid").unwrap()];
+        assert_eq!(None, file.acl().unwrap().access.as_ref());
+        let dest_dir = workdir.path().join("dest");
+        std::fs::create_dir(&dest_dir).unwrap();
+        let report = archive.extract_best_effort(&dest_dir, |_| true).unwrap();
+        assert!(report.is_ok(), "{:?}", report.errors);
+        let mut warnings: Vec<_> = report
+            .warnings
+            .iter()
+            .map(|warning| (warning.path.clone(), warning.kind()))
+            .collect();
+        warnings.sort();
+        assert_eq!(
+            vec![
+                (PathBuf::from("invalid"), ErrorKind::InvalidData),
+                (PathBuf::from("named"), ErrorKind::NotFound)
+            ],
+            warnings
+        );
+        assert_eq!(
+            "contents",
+            std::fs::read_to_string(dest_dir.join("named")).unwrap()
+        );
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        metadata_round_trip_with([
+            random_dir::FileType::Regular,
+            random_dir::FileType::Directory,
+            random_dir::FileType::HardLink,
+        ]);
+        // Hard links to special files are extracted as regular files, i.e. special files are
+        // tested separately. Symlink targets are absolute paths to the source directory, see
+        // `timestamps` test.
+        metadata_round_trip_with([
+            random_dir::FileType::Regular,
+            random_dir::FileType::Directory,
+            random_dir::FileType::Fifo,
+        ]);
+    }
+
+    fn metadata_round_trip_with<const N: usize>(file_types: [random_dir::FileType; N]) {
+        let workdir = TempDir::new().unwrap();
+        arbtest(|u| {
+            let directory = DirBuilder::new()
+                .printable_names(true)
+                .file_types(file_types)
+                .create(u)?;
+            let xar_path = workdir.path().join("test.xar");
+            let mut builder = BuilderOptions::new()
+                .sub_second_timestamps(true)
+                .create(File::create(&xar_path).unwrap(), None::<NoSigner>);
+            builder
+                .append_dir_all(
+                    directory.path(),
+                    Compression::Gzip,
+                    crate::no_extra_contents,
+                )
+                .unwrap();
+            builder.finish().unwrap();
+            let archive = Archive::with_options(
+                File::open(&xar_path).unwrap(),
+                ArchiveOptions::new()
+                    .preserve_mtime(true)
+                    .preserve_atime(true)
+                    .preserve_owner(true),
+            )
+            .unwrap();
+            let dest_dir = workdir.path().join("dest");
+            let _ = std::fs::remove_dir_all(&dest_dir);
+            std::fs::create_dir(&dest_dir).unwrap();
+            archive.extract(&dest_dir).unwrap();
+            let expected = list_dir_all(directory.path()).unwrap();
+            let actual = list_dir_all(&dest_dir).unwrap();
+            similar_asserts::assert_eq!(expected, actual);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn timestamps() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir(&src_dir).unwrap();
+        let before_epoch = SystemTime::UNIX_EPOCH - Duration::new(1_000_000, 250_000_000);
+        let after_epoch = SystemTime::UNIX_EPOCH + Duration::new(1_000_000, 750_000_000);
+        let file = File::create(src_dir.join("file")).unwrap();
+        file.set_times(
+            FileTimes::new()
+                .set_accessed(after_epoch)
+                .set_modified(before_epoch),
+        )
+        .unwrap();
+        drop(file);
+        symlink("file", src_dir.join("symlink")).unwrap();
+        let symlink_path = path_to_c_string(src_dir.join("symlink")).unwrap();
+        let times = [
+            libc::timespec {
+                tv_sec: 1_000_000,
+                tv_nsec: 750_000_000,
+            },
+            libc::timespec {
+                tv_sec: -1_000_001,
+                tv_nsec: 750_000_000,
+            },
+        ];
+        assert_eq!(0, unsafe {
+            libc::utimensat(
+                libc::AT_FDCWD,
+                symlink_path.as_ptr(),
+                times.as_ptr(),
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        });
+        let xar_path = workdir.path().join("test.xar");
+        // whole seconds by default
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        let file = &archive.files()[archive.find("file").unwrap()];
+        assert_eq!(
+            SystemTime::UNIX_EPOCH - Duration::from_secs(1_000_001),
+            file.mtime.0
+        );
+        assert_eq!(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000),
+            file.atime.0
+        );
+        // sub-second precision
+        let mut builder = BuilderOptions::new()
+            .sub_second_timestamps(true)
+            .create(File::create(&xar_path).unwrap(), None::<NoSigner>);
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        let archive = Archive::with_options(
+            File::open(&xar_path).unwrap(),
+            ArchiveOptions::new()
+                .preserve_mtime(true)
+                .preserve_atime(true),
+        )
+        .unwrap();
+        let file = &archive.files()[archive.find("file").unwrap()];
+        assert_eq!(before_epoch, file.mtime.0);
+        assert_eq!(after_epoch, file.atime.0);
+        // Resolving the symlink while archiving updates its access time.
+        let symlink_atime = archive.files()[archive.find("symlink").unwrap()].atime.0;
+        let dest_dir = workdir.path().join("dest");
+        std::fs::create_dir(&dest_dir).unwrap();
+        archive.extract(&dest_dir).unwrap();
+        let metadata = dest_dir.join("file").metadata().unwrap();
+        assert_eq!(before_epoch, metadata.modified().unwrap());
+        assert_eq!(after_epoch, metadata.accessed().unwrap());
+        assert_eq!(-1_000_001, metadata.mtime());
+        let metadata = dest_dir.join("symlink").symlink_metadata().unwrap();
+        assert_eq!(before_epoch, metadata.modified().unwrap());
+        assert_eq!(symlink_atime, metadata.accessed().unwrap());
+        assert_eq!(
+            "1969-12-20T10:13:19.750Z",
+            String::from(Timestamp(before_epoch))
+        );
+        assert_eq!("1970-01-01T00:00:00Z", String::from(Timestamp::default()));
+    }
+
+    #[test]
+    fn permissions() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir_all(src_dir.join("dir")).unwrap();
+        std::fs::write(src_dir.join("file"), "contents").unwrap();
+        std::fs::set_permissions(src_dir.join("file"), Permissions::from_mode(0o4755)).unwrap();
+        std::fs::set_permissions(src_dir.join("dir"), Permissions::from_mode(0o3775)).unwrap();
+        // The process umask applies to the mode passed to `mkfifo` and `mknod`.
+        let fifo = path_to_c_string(src_dir.join("fifo")).unwrap();
+        assert_eq!(0, unsafe { libc::mkfifo(fifo.as_ptr(), 0o666) });
+        let device = path_to_c_string(src_dir.join("device")).unwrap();
+        let has_device = unsafe {
+            libc::mknod(
+                device.as_ptr(),
+                libc::S_IFCHR | 0o666,
+                libc::makedev(1, 3) as _,
+            )
+        } == 0;
+        let mut special_files = vec!["fifo"];
+        if has_device {
+            special_files.push("device");
+        }
+        for name in special_files.iter() {
+            std::fs::set_permissions(src_dir.join(name), Permissions::from_mode(0o666)).unwrap();
+        }
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        for (i, (options, file_mode, dir_mode, special_mode)) in [
+            (ArchiveOptions::new(), 0o755, 0o3775, 0o666),
+            (
+                ArchiveOptions::new().keep_setuid(true),
+                0o4755,
+                0o3775,
+                0o666,
+            ),
+            (ArchiveOptions::new().umask(0o027), 0o750, 0o3750, 0o640),
+            (
+                ArchiveOptions::new().same_permissions(false).umask(0o022),
+                0o644,
+                0o755,
+                0o644,
+            ),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let archive = Archive::with_options(File::open(&xar_path).unwrap(), options).unwrap();
+            let dest_dir = workdir.path().join(i.to_string());
+            std::fs::create_dir(&dest_dir).unwrap();
+            archive.extract(&dest_dir).unwrap();
+            let mode = |name| dest_dir.join(name).metadata().unwrap().mode() & 0o7777;
+            assert_eq!(file_mode, mode("file"), "i = {i}");
+            assert_eq!(dir_mode, mode("dir"), "i = {i}");
+            for name in special_files.iter() {
+                assert_eq!(special_mode, mode(name), "i = {i}, name = {name}");
+            }
+        }
+    }
+
+    #[test]
+    fn owner_names() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir(&src_dir).unwrap();
+        File::create(src_dir.join("file")).unwrap();
+        let uid = unsafe { libc::getuid() };
+        let gid = unsafe { libc::getgid() };
+        let (Some(user), Some(group)) = (user_name(uid).unwrap(), group_name(gid).unwrap()) else {
+            // no passwd/group entries
+            return;
+        };
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        for (i, (name, user)) in [("known", user.as_str()), ("unknown", "no-such-zar-user")]
+            .into_iter()
+            .enumerate()
+        {
+            let (mut file, contents) = xml::File::<()>::new(
+                i as u64 + 1,
+                "",
+                src_dir.join("file"),
+                name.into(),
+                Compression::None,
+                ChecksumAlgo::Sha256,
+                0,
+                None,
+            )
+            .unwrap();
+            assert_eq!(Some(group.as_str()), file.group.as_deref());
+            file.inode = i as u64 + 1;
+            file.uid = 54321;
+            file.gid = 54321;
+            file.user = Some(user.into());
+            builder.append_raw(file, contents).unwrap();
+        }
+        builder.finish().unwrap();
+        let is_root = uid == 0;
+        for numeric_owner in [false, true] {
+            if numeric_owner && !is_root {
+                continue;
+            }
+            let archive = Archive::with_options(
+                File::open(&xar_path).unwrap(),
+                ArchiveOptions::new()
+                    .preserve_owner(true)
+                    .numeric_owner(numeric_owner),
+            )
+            .unwrap();
+            let dest_dir = workdir.path().join(format!("dest-{numeric_owner}"));
+            std::fs::create_dir(&dest_dir).unwrap();
+            let result = archive.extract(&dest_dir);
+            let known = dest_dir.join("known").metadata().unwrap();
+            if numeric_owner {
+                result.unwrap();
+                assert_eq!((54321, 54321), (known.uid(), known.gid()));
+            } else {
+                assert_eq!((uid, gid), (known.uid(), known.gid()));
+                if is_root {
+                    result.unwrap();
+                    let unknown = dest_dir.join("unknown").metadata().unwrap();
+                    assert_eq!((54321, gid), (unknown.uid(), unknown.gid()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn owner_map() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir(&src_dir).unwrap();
+        File::create(src_dir.join("file")).unwrap();
+        let uid = unsafe { libc::getuid() };
+        let gid = unsafe { libc::getgid() };
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = BuilderOptions::new()
+            .numeric_owner(true)
+            .owner_map(
+                OwnerMap::new()
+                    .user(4321, Some("zar-user".into()))
+                    .map_gid(gid, 4321),
+            )
+            .create(File::create(&xar_path).unwrap(), None::<NoSigner>);
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        let archive = Archive::with_options(
+            File::open(&xar_path).unwrap(),
+            ArchiveOptions::new().preserve_owner(true).owner_map(
+                OwnerMap::new()
+                    .map_user("zar-user", "no-such-zar-user")
+                    .map_uid(4321, uid)
+                    .map_gid(4321, gid),
+            ),
+        )
+        .unwrap();
+        let file = &archive.files()[archive.find("file").unwrap()];
+        assert_eq!(
+            (4321, Some("zar-user"), 4321, None),
+            (
+                file.uid,
+                file.user.as_deref(),
+                file.gid,
+                file.group.as_deref()
+            )
+        );
+        let dest_dir = workdir.path().join("dest");
+        std::fs::create_dir(&dest_dir).unwrap();
+        archive.extract(&dest_dir).unwrap();
+        let metadata = dest_dir.join("file").metadata().unwrap();
+        assert_eq!((uid, gid), (metadata.uid(), metadata.gid()));
+    }
+
+    #[test]
+    fn hard_link_modes() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir(&src_dir).unwrap();
+        std::fs::write(src_dir.join("original"), "contents").unwrap();
+        std::fs::set_permissions(src_dir.join("original"), Permissions::from_mode(0o640)).unwrap();
+        let xattrs_supported = match set_xattr(
+            &path_to_c_string(src_dir.join("original")).unwrap(),
+            c"user.a",
+            b"a",
+        ) {
+            Err(e) if e.kind() == ErrorKind::Unsupported => false,
+            other => {
+                other.unwrap();
+                true
+            }
+        };
+        std::fs::hard_link(src_dir.join("original"), src_dir.join("link")).unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        for (mode, linked) in [
+            (HardLinkMode::Link, true),
+            (HardLinkMode::Copy, false),
+            (HardLinkMode::LinkOrCopy, true),
+        ] {
+            let archive = Archive::with_options(
+                File::open(&xar_path).unwrap(),
+                ArchiveOptions::new().hard_links(mode),
+            )
+            .unwrap();
+            let dest_dir = workdir.path().join(format!("{mode:?}"));
+            std::fs::create_dir(&dest_dir).unwrap();
+            archive.extract(&dest_dir).unwrap();
+            let original = dest_dir.join("original").metadata().unwrap();
+            let link = dest_dir.join("link").metadata().unwrap();
+            assert_eq!(linked, original.ino() == link.ino(), "mode = {mode:?}");
+            assert_eq!(0o640, link.mode() & 0o777, "mode = {mode:?}");
+            assert_eq!(
+                "contents",
+                std::fs::read_to_string(dest_dir.join("link")).unwrap()
+            );
+            if xattrs_supported {
+                assert_eq!(
+                    b"a".to_vec(),
+                    get_xattr(&path_to_c_string(dest_dir.join("link")).unwrap(), c"user.a")
+                        .unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hard_link_copy_from_archive() {
+        const LEN: u64 = 1024 * 1024;
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir(&src_dir).unwrap();
+        let mut file = File::create(src_dir.join("original")).unwrap();
+        file.set_len(LEN).unwrap();
+        file.seek(SeekFrom::Start(LEN / 2)).unwrap();
+        file.write_all(b"data").unwrap();
+        let atime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        file.set_times(FileTimes::new().set_accessed(atime))
+            .unwrap();
+        drop(file);
+        // write-only
+        std::fs::set_permissions(src_dir.join("original"), Permissions::from_mode(0o200)).unwrap();
+        std::fs::hard_link(src_dir.join("original"), src_dir.join("link")).unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        let archive = Archive::with_options(
+            File::open(&xar_path).unwrap(),
+            ArchiveOptions::new()
+                .hard_links(HardLinkMode::Copy)
+                .preserve_atime(true),
+        )
+        .unwrap();
+        let sparse = !archive.files()[archive.find("original").unwrap()]
+            .data()
+            .unwrap()
+            .holes
+            .is_empty();
+        let dest_dir = workdir.path().join("dest");
+        std::fs::create_dir(&dest_dir).unwrap();
+        archive.extract(&dest_dir).unwrap();
+        let original = dest_dir.join("original").metadata().unwrap();
+        let link = dest_dir.join("link").metadata().unwrap();
+        assert_ne!(original.ino(), link.ino());
+        assert_eq!(0o200, link.mode() & 0o777);
+        assert_eq!(LEN, link.len());
+        assert_eq!(atime, original.accessed().unwrap());
+        assert_eq!(atime, link.accessed().unwrap());
+        if sparse {
+            assert!(link.blocks() * 512 < LEN, "the copy is not sparse");
+        }
+    }
+
+    #[test]
+    fn sparse_files() {
+        const LEN: u64 = 1024 * 1024;
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir(&src_dir).unwrap();
+        let mut file = File::create(src_dir.join("sparse")).unwrap();
+        file.set_len(LEN).unwrap();
+        file.seek(SeekFrom::Start(LEN / 2)).unwrap();
+        file.write_all(b"data").unwrap();
+        drop(file);
+        File::create(src_dir.join("trailing-hole"))
+            .unwrap()
+            .set_len(LEN)
+            .unwrap();
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        let archive = Archive::new(File::open(&xar_path).unwrap()).unwrap();
+        let holes = &archive.files()[archive.find("sparse").unwrap()]
+            .data()
+            .unwrap()
+            .holes;
+        if holes.is_empty() {
+            // the file system doesn't support holes
+            return;
+        }
+        assert_eq!(2, holes.len());
+        let dest_dir = workdir.path().join("dest");
+        std::fs::create_dir(&dest_dir).unwrap();
+        archive.extract(&dest_dir).unwrap();
+        for name in ["sparse", "trailing-hole"] {
+            assert_eq!(
+                std::fs::read(src_dir.join(name)).unwrap(),
+                std::fs::read(dest_dir.join(name)).unwrap()
+            );
+            let metadata = dest_dir.join(name).metadata().unwrap();
+            assert_eq!(LEN, metadata.len());
+            assert!(metadata.blocks() * 512 < LEN, "{name} is not sparse");
+        }
+    }
+
+    #[test]
+    fn file_flags() {
+        let workdir = TempDir::new().unwrap();
+        let src_dir = workdir.path().join("src");
+        std::fs::create_dir_all(src_dir.join("dir")).unwrap();
+        std::fs::write(src_dir.join("file"), "contents").unwrap();
+        let flags = |path: PathBuf| get_file_flags(File::open(path).unwrap().as_fd());
+        match set_file_flags(
+            File::open(src_dir.join("file")).unwrap().as_fd(),
+            FileFlags::USER_NO_DUMP,
+        ) {
+            Err(e) if matches!(e.raw_os_error(), Some(libc::ENOTTY | libc::ENOTSUP)) => return,
+            other => other.unwrap(),
+        }
+        let xar_path = workdir.path().join("test.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        builder
+            .append_dir_all(&src_dir, Compression::Gzip, crate::no_extra_contents)
+            .unwrap();
+        builder.finish().unwrap();
+        for file_flags in [false, true] {
+            let archive = Archive::with_options(
+                File::open(&xar_path).unwrap(),
+                ArchiveOptions::new().file_flags(file_flags),
+            )
+            .unwrap();
+            let file = &archive.files()[archive.find("file").unwrap()];
+            assert_eq!(FileFlags::USER_NO_DUMP, file.flags);
+            let dir = &archive.files()[archive.find("dir").unwrap()];
+            assert!(dir.flags.is_empty());
+            let dest_dir = workdir.path().join(format!("dest-{file_flags}"));
+            std::fs::create_dir(&dest_dir).unwrap();
+            archive.extract(&dest_dir).unwrap();
+            assert_eq!(
+                file_flags,
+                flags(dest_dir.join("file"))
+                    .unwrap()
+                    .contains(FileFlags::USER_NO_DUMP)
+            );
+        }
+    }
+
+    fn set_modified(path: &Path, mtime: SystemTime) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    /// Create an archive with the specified source files and archive names.
+    ///
+    /// The symlink points to "outside" directory.
+    fn hostile_archive(
+        workdir: &Path,
+        files: &[(&str, &str)],
+        options: ArchiveOptions,
+    ) -> Archive<File> {
+        let src_dir = workdir.join("src");
+        std::fs::create_dir_all(&src_dir).unwrap();
+        File::create(src_dir.join("file")).unwrap();
+        symlink(workdir.join("outside"), src_dir.join("symlink")).ok();
+        let xar_path = workdir.join("hostile.xar");
+        let mut builder = UnsignedBuilder::new_unsigned(File::create(&xar_path).unwrap());
+        for (i, (src, name)) in files.iter().enumerate() {
+            let (mut file, contents) = xml::File::<()>::new(
+                i as u64 + 1,
+                "",
+                src_dir.join(src),
+                (*name).into(),
+                Compression::None,
+                ChecksumAlgo::Sha256,
+                0,
+                None,
+            )
+            .unwrap();
+            // make all files distinct
+            file.inode = i as u64 + 1;
+            builder.append_raw(file, contents).unwrap();
+        }
+        builder.finish().unwrap();
+        Archive::with_options(File::open(&xar_path).unwrap(), options).unwrap()
+    }
+}
@@ -0,0 +1,246 @@
+use std::fmt::Formatter;
+use std::ops::BitOr;
+use std::ops::BitOrAssign;
+
+use serde::de::IgnoredAny;
+use serde::de::MapAccess;
+use serde::de::Visitor;
+use serde::ser::SerializeStruct;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+
+/// File flags.
+///
+/// BSD file flags as defined by `chflags(2)`. On Linux some of them are mapped to inode
+/// attributes (see `ioctl_iflags(2)`).
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct FileFlags(u32);
+
+impl FileFlags {
+    /// Do not dump the file.
+    pub const USER_NO_DUMP: Self = Self(0x1);
+    /// The file may not be changed.
+    pub const USER_IMMUTABLE: Self = Self(0x2);
+    /// The file may only be appended to.
+    pub const USER_APPEND: Self = Self(0x4);
+    /// The directory is opaque when viewed through a union mount.
+    pub const USER_OPAQUE: Self = Self(0x8);
+    /// The file may not be removed or renamed.
+    pub const USER_NO_UNLINK: Self = Self(0x10);
+    /// The file is archived.
+    pub const SYSTEM_ARCHIVED: Self = Self(0x10000);
+    /// The file may not be changed (can only be set by the super-user).
+    pub const SYSTEM_IMMUTABLE: Self = Self(0x20000);
+    /// The file may only be appended to (can only be set by the super-user).
+    pub const SYSTEM_APPEND: Self = Self(0x40000);
+    /// The file may not be removed or renamed (can only be set by the super-user).
+    pub const SYSTEM_NO_UNLINK: Self = Self(0x100000);
+
+    /// Create flags from the raw value.
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// Get raw value.
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns `true` if no flags are set.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if any of the `other` flags are set.
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Returns `true` if all of the `other` flags are set.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Convert Linux inode attributes to file flags.
+    ///
+    /// Only immutable, append-only and no-dump attributes are converted.
+    /// Immutable and append-only attributes are converted to system flags because only
+    /// privileged processes can change them.
+    pub fn from_linux(attrs: u32) -> Self {
+        let mut flags = Self::default();
+        for (attr, flag, _) in LINUX_ATTRS {
+            if attrs & attr != 0 {
+                flags |= flag;
+            }
+        }
+        flags
+    }
+
+    /// Convert file flags to Linux inode attributes.
+    ///
+    /// Only immutable, append-only and no-dump flags are converted.
+    /// Linux doesn't distinguish between user and system flags.
+    pub fn to_linux(self) -> u32 {
+        let mut attrs = 0;
+        for (attr, _, flags) in LINUX_ATTRS {
+            if self.intersects(flags) {
+                attrs |= attr;
+            }
+        }
+        attrs
+    }
+}
+
+impl BitOr for FileFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self::Output {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for FileFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl Serialize for FileFlags {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let names = NAMES.iter().filter(|(flag, _)| self.contains(*flag));
+        let mut state = serializer.serialize_struct("flags", names.clone().count())?;
+        for (_, name) in names {
+            state.serialize_field(name, &())?;
+        }
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for FileFlags {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(FileFlagsVisitor)
+    }
+}
+
+struct FileFlagsVisitor;
+
+impl<'de> Visitor<'de> for FileFlagsVisitor {
+    type Value = FileFlags;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("file flags")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut flags = FileFlags::default();
+        while let Some(name) = map.next_key::<String>()? {
+            map.next_value::<IgnoredAny>()?;
+            // ignore unknown flags
+            if let Some((flag, _)) = NAMES.iter().find(|(_, n)| *n == name) {
+                flags |= *flag;
+            }
+        }
+        Ok(flags)
+    }
+
+    fn visit_str<E>(self, _value: &str) -> Result<Self::Value, E> {
+        // empty element
+        Ok(FileFlags::default())
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(FileFlags::default())
+    }
+}
+
+const NAMES: [(FileFlags, &str); 9] = [
+    (FileFlags::USER_NO_DUMP, "UserNoDump"),
+    (FileFlags::USER_IMMUTABLE, "UserImmutable"),
+    (FileFlags::USER_APPEND, "UserAppend"),
+    (FileFlags::USER_OPAQUE, "UserOpaque"),
+    (FileFlags::USER_NO_UNLINK, "UserNoUnlink"),
+    (FileFlags::SYSTEM_ARCHIVED, "SystemArchived"),
+    (FileFlags::SYSTEM_IMMUTABLE, "SystemImmutable"),
+    (FileFlags::SYSTEM_APPEND, "SystemAppend"),
+    (FileFlags::SYSTEM_NO_UNLINK, "SystemNoUnlink"),
+];
+
+// (Linux attribute, the flag it is converted to, the flags that are converted to it)
+const LINUX_ATTRS: [(u32, FileFlags, FileFlags); 3] = [
+    (
+        FS_IMMUTABLE_FL,
+        FileFlags::SYSTEM_IMMUTABLE,
+        FileFlags(FileFlags::USER_IMMUTABLE.0 | FileFlags::SYSTEM_IMMUTABLE.0),
+    ),
+    (
+        FS_APPEND_FL,
+        FileFlags::SYSTEM_APPEND,
+        FileFlags(FileFlags::USER_APPEND.0 | FileFlags::SYSTEM_APPEND.0),
+    ),
+    (
+        FS_NODUMP_FL,
+        FileFlags::USER_NO_DUMP,
+        FileFlags::USER_NO_DUMP,
+    ),
+];
+
+const FS_IMMUTABLE_FL: u32 = 0x10;
+const FS_APPEND_FL: u32 = 0x20;
+const FS_NODUMP_FL: u32 = 0x40;
+
+#[cfg(test)]
+mod tests {
+    use quick_xml::de::from_str;
+    use quick_xml::se::to_string;
+
+    use super::*;
+
+    #[test]
+    fn xml_symmetry() {
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+        #[serde(rename = "file")]
+        struct File {
+            #[serde(default, skip_serializing_if = "FileFlags::is_empty")]
+            flags: FileFlags,
+        }
+        let expected = File {
+            flags: FileFlags::USER_NO_DUMP | FileFlags::SYSTEM_IMMUTABLE,
+        };
+        let xml = to_string(&expected).unwrap();
+        assert_eq!(
+            "<file><flags><UserNoDump/><SystemImmutable/></flags></file>",
+            xml
+        );
+        assert_eq!(expected, from_str(&xml).unwrap());
+        let empty = File {
+            flags: Default::default(),
+        };
+        assert_eq!("<file/>", to_string(&empty).unwrap());
+        assert_eq!(empty, from_str("<file><flags/></file>").unwrap());
+        assert_eq!(
+            empty,
+            from_str("<file><flags><Unknown/></flags></file>").unwrap()
+        );
+    }
+
+    #[test]
+    fn linux() {
+        let flags = FileFlags::USER_NO_DUMP | FileFlags::USER_IMMUTABLE | FileFlags::SYSTEM_APPEND;
+        assert_eq!(
+            FileFlags::USER_NO_DUMP | FileFlags::SYSTEM_IMMUTABLE | FileFlags::SYSTEM_APPEND,
+            FileFlags::from_linux(flags.to_linux())
+        );
+        assert_eq!(0, FileFlags::USER_OPAQUE.to_linux());
+    }
+}
@@ -0,0 +1,224 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use glob::MatchOptions;
+use glob::Pattern;
+
+/// Selects files by their archive paths.
+///
+/// A path is selected if it matches any of the include patterns (or there are no include
+/// patterns) and does not match any of the exclude patterns.
+///
+/// A pattern matches a path if it matches the path itself or any of its parent directories,
+/// i.e. directories are selected together with their contents.
+/// Exclude patterns that do not contain `/` are matched against every path component instead,
+/// e.g. `.git`, `*.o` or `.DS_Store` exclude such files everywhere in the tree.
+#[derive(Clone, Debug, Default)]
+pub struct PathFilter {
+    include: Vec<Matcher>,
+    exclude: Vec<Matcher>,
+}
+
+impl PathFilter {
+    /// Create new filter that selects every path.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Select the paths that match glob `pattern`.
+    pub fn include(mut self, pattern: &str) -> Result<Self, Error> {
+        self.include.push(Matcher::glob(pattern)?);
+        Ok(self)
+    }
+
+    /// Select the specified path verbatim, i.e. without interpreting it as a glob pattern.
+    pub fn include_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.include.push(Matcher::Path(normalize(path.as_ref())));
+        self
+    }
+
+    /// Do not select the paths that match glob `pattern`.
+    pub fn exclude(mut self, pattern: &str) -> Result<Self, Error> {
+        self.exclude.push(Matcher::glob(pattern)?);
+        Ok(self)
+    }
+
+    /// Do not select the specified path.
+    pub fn exclude_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.exclude.push(Matcher::Path(normalize(path.as_ref())));
+        self
+    }
+
+    /// Returns `true` if the filter selects the path.
+    pub fn matches<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        self.includes(path) && !self.excludes(path)
+    }
+
+    /// Returns `true` if the path matches any of the include patterns or there are no include
+    /// patterns.
+    pub(crate) fn includes(&self, path: &Path) -> bool {
+        let path = normalize(path);
+        self.include.is_empty() || self.include.iter().any(|m| m.matches(&path, false))
+    }
+
+    /// Returns `true` if the path matches any of the exclude patterns.
+    ///
+    /// The contents of the excluded directories are excluded as well.
+    pub(crate) fn excludes(&self, path: &Path) -> bool {
+        let path = normalize(path);
+        self.exclude.iter().any(|m| m.matches(&path, true))
+    }
+}
+
+/// Selects extended attributes by their names.
+///
+/// A name is selected if it matches any of the include patterns (or there are no include
+/// patterns) and does not match any of the exclude patterns,
+/// e.g. exclude `com.apple.quarantine` to drop quarantine flags.
+#[derive(Clone, Debug, Default)]
+pub struct XattrFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl XattrFilter {
+    /// Create new filter that selects every name.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Select the names that match glob `pattern`.
+    pub fn include(mut self, pattern: &str) -> Result<Self, Error> {
+        self.include.push(new_pattern(pattern)?);
+        Ok(self)
+    }
+
+    /// Do not select the names that match glob `pattern`.
+    pub fn exclude(mut self, pattern: &str) -> Result<Self, Error> {
+        self.exclude.push(new_pattern(pattern)?);
+        Ok(self)
+    }
+
+    /// Returns `true` if the filter selects the name.
+    pub fn matches(&self, name: &str) -> bool {
+        if !self.include.is_empty() && !self.include.iter().any(|p| p.matches(name)) {
+            return false;
+        }
+        !self.exclude.iter().any(|p| p.matches(name))
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    Glob {
+        pattern: Pattern,
+        // match against the full path rather than individual components
+        anchored: bool,
+    },
+    Path(PathBuf),
+}
+
+impl Matcher {
+    fn glob(pattern: &str) -> Result<Self, Error> {
+        let anchored = pattern.contains('/');
+        let pattern = normalize(Path::new(pattern));
+        let pattern = pattern.to_str().ok_or(ErrorKind::InvalidData)?;
+        let pattern = new_pattern(pattern)?;
+        Ok(Self::Glob { pattern, anchored })
+    }
+
+    fn matches(&self, path: &Path, unanchored: bool) -> bool {
+        match self {
+            Self::Glob { pattern, anchored } => {
+                if unanchored && !anchored {
+                    path.components()
+                        .any(|c| pattern.matches_path_with(Path::new(&c), MATCH_OPTIONS))
+                } else {
+                    path.ancestors()
+                        .any(|p| pattern.matches_path_with(p, MATCH_OPTIONS))
+                }
+            }
+            Self::Path(expected) => path.ancestors().any(|p| p == expected),
+        }
+    }
+}
+
+fn new_pattern(pattern: &str) -> Result<Pattern, Error> {
+    Pattern::new(pattern).map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+}
+
+/// Strip leading `/` and `.` path components.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| matches!(c, Component::Normal(..) | Component::ParentDir))
+        .collect()
+}
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_exclude() {
+        let filter = PathFilter::new();
+        assert!(filter.matches("a/b/c"));
+        let filter = PathFilter::new().include("a/b").unwrap();
+        assert!(filter.matches("a/b"));
+        assert!(filter.matches("a/b/c"));
+        assert!(filter.matches("/a/b/c"));
+        assert!(!filter.matches("a"));
+        assert!(!filter.matches("a/bc"));
+        assert!(!filter.matches("x/a/b"));
+        let filter = PathFilter::new().include("*.txt").unwrap();
+        assert!(filter.matches("a.txt"));
+        assert!(!filter.matches("a/b.txt"));
+        let filter = PathFilter::new()
+            .include_path("a[1]")
+            .exclude("*.o")
+            .unwrap();
+        assert!(filter.matches("a[1]/b"));
+        assert!(!filter.matches("a1/b"));
+        assert!(!filter.matches("a[1]/b.o"));
+        let filter = PathFilter::new()
+            .exclude(".git")
+            .unwrap()
+            .exclude("a/*.o")
+            .unwrap()
+            .exclude_path("Payload");
+        assert!(!filter.matches(".git"));
+        assert!(!filter.matches("a/.git/config"));
+        assert!(!filter.matches("a/b.o"));
+        assert!(filter.matches("b/b.o"));
+        assert!(!filter.matches("Payload/file"));
+        assert!(filter.matches("b/Payload"));
+        assert!(PathFilter::new().include("[").is_err());
+    }
+
+    #[test]
+    fn xattr_filter() {
+        let filter = XattrFilter::new();
+        assert!(filter.matches("user.a"));
+        let filter = XattrFilter::new()
+            .include("user.*")
+            .unwrap()
+            .exclude("user.b*")
+            .unwrap();
+        assert!(filter.matches("user.a"));
+        assert!(!filter.matches("user.b"));
+        assert!(!filter.matches("user.bc"));
+        assert!(!filter.matches("trusted.a"));
+        let filter = XattrFilter::new().exclude("com.apple.quarantine").unwrap();
+        assert!(!filter.matches("com.apple.quarantine"));
+        assert!(filter.matches("com.apple.FinderInfo"));
+    }
+}
@@ -1,15 +1,20 @@
 #![doc = include_str!("../README.md")]
 
+mod acl;
 #[cfg(feature = "apple-root-cert")]
 mod apple;
 mod archive;
 mod builder;
 mod checksum;
 mod compression;
+mod extract;
+mod file_flags;
 mod file_mode;
 mod file_type;
+mod filter;
 mod header;
 mod mk;
+mod owner;
 mod root_certs;
 mod rsa_signer;
 mod signer;
@@ -20,24 +25,34 @@ mod xml;
 pub use rsa;
 pub use x509_cert;
 
+pub use self::acl::*;
 #[cfg(feature = "apple-root-cert")]
 pub use self::apple::*;
 pub use self::archive::*;
 pub use self::builder::*;
 pub use self::checksum::*;
 pub use self::compression::*;
+pub(crate) use self::extract::*;
+pub use self::file_flags::*;
 pub use self::file_mode::*;
 pub use self::file_type::*;
+pub use self::filter::*;
 pub(crate) use self::header::*;
+pub use self::mk::group_id;
+pub use self::mk::user_id;
 pub(crate) use self::mk::*;
+pub use self::owner::*;
 pub use self::root_certs::*;
 pub use self::rsa_signer::*;
 pub use self::signer::*;
 pub(crate) use self::walk::*;
 pub use self::xml::Device;
 pub use self::xml::Encoding;
+pub use self::xml::ExtendedAttribute;
 pub use self::xml::File;
+pub use self::xml::FileAcl;
 pub use self::xml::FileChecksum;
 pub use self::xml::FileData;
+pub use self::xml::Hole;
 pub use self::xml::Link;
 pub use self::xml::Timestamp;
@@ -1,58 +1,260 @@
 use std::ffi::CStr;
 use std::ffi::CString;
 use std::io::Error;
+use std::mem::MaybeUninit;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::IntoRawFd;
+use std::os::fd::OwnedFd;
 use std::os::unix::ffi::OsStringExt;
 use std::path::PathBuf;
 use std::time::SystemTime;
 
+use libc::c_int;
+use libc::c_uint;
 use libc::dev_t;
 use libc::gid_t;
 use libc::mode_t;
 use libc::uid_t;
-use libc::AT_FDCWD;
 use libc::AT_SYMLINK_NOFOLLOW;
+use libc::O_CLOEXEC;
 use libc::UTIME_OMIT;
 
-pub fn mkfifo(path: &CStr, mode: mode_t) -> Result<(), Error> {
-    let ret = unsafe { libc::mkfifo(path.as_ptr(), mode) };
+use crate::FileFlags;
+
+pub fn open_at(
+    dirfd: BorrowedFd,
+    path: &CStr,
+    flags: c_int,
+    mode: mode_t,
+) -> Result<OwnedFd, Error> {
+    let fd = unsafe {
+        libc::openat(
+            dirfd.as_raw_fd(),
+            path.as_ptr(),
+            flags | O_CLOEXEC,
+            mode as c_uint,
+        )
+    };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+pub fn mkdir_at(dirfd: BorrowedFd, path: &CStr, mode: mode_t) -> Result<(), Error> {
+    let ret = unsafe { libc::mkdirat(dirfd.as_raw_fd(), path.as_ptr(), mode) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn mkfifo_at(dirfd: BorrowedFd, path: &CStr, mode: mode_t) -> Result<(), Error> {
+    let ret = unsafe { libc::mkfifoat(dirfd.as_raw_fd(), path.as_ptr(), mode) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn mknod_at(dirfd: BorrowedFd, path: &CStr, mode: mode_t, dev: dev_t) -> Result<(), Error> {
+    let ret = unsafe { libc::mknodat(dirfd.as_raw_fd(), path.as_ptr(), mode, dev) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn chmod_at(dirfd: BorrowedFd, path: &CStr, mode: mode_t) -> Result<(), Error> {
+    let ret = unsafe { libc::fchmodat(dirfd.as_raw_fd(), path.as_ptr(), mode, 0) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn symlink_at(target: &CStr, dirfd: BorrowedFd, path: &CStr) -> Result<(), Error> {
+    let ret = unsafe { libc::symlinkat(target.as_ptr(), dirfd.as_raw_fd(), path.as_ptr()) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn link_at(
+    old_dirfd: BorrowedFd,
+    old_path: &CStr,
+    new_dirfd: BorrowedFd,
+    new_path: &CStr,
+) -> Result<(), Error> {
+    let ret = unsafe {
+        libc::linkat(
+            old_dirfd.as_raw_fd(),
+            old_path.as_ptr(),
+            new_dirfd.as_raw_fd(),
+            new_path.as_ptr(),
+            0,
+        )
+    };
     if ret < 0 {
         return Err(Error::last_os_error());
     }
     Ok(())
 }
 
-pub fn mknod(path: &CStr, mode: mode_t, dev: dev_t) -> Result<(), Error> {
-    let ret = unsafe { libc::mknod(path.as_ptr(), mode, dev) };
+pub fn unlink_at(dirfd: BorrowedFd, path: &CStr, flags: c_int) -> Result<(), Error> {
+    let ret = unsafe { libc::unlinkat(dirfd.as_raw_fd(), path.as_ptr(), flags) };
     if ret < 0 {
         return Err(Error::last_os_error());
     }
     Ok(())
 }
 
-pub fn set_file_modified_time(path: &CStr, t: SystemTime) -> Result<(), Error> {
-    let Ok(d) = t.duration_since(SystemTime::UNIX_EPOCH) else {
-        return Ok(());
+/// Remove directory `path` and its contents without following symbolic links.
+pub fn remove_dir_all_at(dirfd: BorrowedFd, path: &CStr) -> Result<(), Error> {
+    let dir = open_at(
+        dirfd,
+        path,
+        libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW,
+        0,
+    )?;
+    for name in read_dir_at(dir.as_fd())?.into_iter() {
+        let stat = lstat_at(dir.as_fd(), &name)?;
+        if stat.st_mode & libc::S_IFMT == libc::S_IFDIR {
+            remove_dir_all_at(dir.as_fd(), &name)?;
+        } else {
+            unlink_at(dir.as_fd(), &name, 0)?;
+        }
+    }
+    drop(dir);
+    unlink_at(dirfd, path, libc::AT_REMOVEDIR)
+}
+
+/// List the names of the entries of directory `dirfd` excluding `.` and `..`.
+fn read_dir_at(dirfd: BorrowedFd) -> Result<Vec<CString>, Error> {
+    let fd = dirfd.try_clone_to_owned()?;
+    let dir = unsafe { libc::fdopendir(fd.as_raw_fd()) };
+    if dir.is_null() {
+        return Err(Error::last_os_error());
+    }
+    // `closedir` closes the file descriptor.
+    let _ = fd.into_raw_fd();
+    // The duplicate shares the offset with the original descriptor.
+    unsafe { libc::rewinddir(dir) };
+    let mut names = Vec::new();
+    let result = loop {
+        unsafe { *errno_location() = 0 };
+        let entry = unsafe { libc::readdir(dir) };
+        if entry.is_null() {
+            match Error::last_os_error() {
+                e if e.raw_os_error() == Some(0) => break Ok(names),
+                e => break Err(e),
+            }
+        }
+        let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+        if name != c"." && name != c".." {
+            names.push(name.to_owned());
+        }
+    };
+    unsafe { libc::closedir(dir) };
+    result
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__errno_location()
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__error()
+}
+
+pub fn lstat_at(dirfd: BorrowedFd, path: &CStr) -> Result<libc::stat, Error> {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+    let ret = unsafe {
+        libc::fstatat(
+            dirfd.as_raw_fd(),
+            path.as_ptr(),
+            stat.as_mut_ptr(),
+            AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(unsafe { stat.assume_init() })
+}
+
+pub fn set_file_times_at(
+    dirfd: BorrowedFd,
+    path: &CStr,
+    atime: Option<SystemTime>,
+    mtime: Option<SystemTime>,
+) -> Result<(), Error> {
+    let times = [to_timespec(atime), to_timespec(mtime)];
+    let ret = unsafe {
+        libc::utimensat(
+            dirfd.as_raw_fd(),
+            path.as_ptr(),
+            times.as_ptr(),
+            AT_SYMLINK_NOFOLLOW,
+        )
     };
-    let times = [
-        libc::timespec {
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn to_timespec(t: Option<SystemTime>) -> libc::timespec {
+    let Some(t) = t else {
+        return libc::timespec {
             tv_sec: 0,
             tv_nsec: UTIME_OMIT,
-        },
-        libc::timespec {
+        };
+    };
+    match t.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => libc::timespec {
             tv_sec: d.as_secs() as libc::time_t,
             tv_nsec: d.subsec_nanos() as libc::c_long,
         },
-    ];
-    let ret =
-        unsafe { libc::utimensat(AT_FDCWD, path.as_ptr(), times.as_ptr(), AT_SYMLINK_NOFOLLOW) };
+        Err(e) => {
+            // before the epoch; nanoseconds are always non-negative
+            let d = e.duration();
+            let (secs, nanos) = match d.subsec_nanos() {
+                0 => (d.as_secs() as libc::time_t, 0),
+                nanos => (d.as_secs() as libc::time_t + 1, 1_000_000_000 - nanos),
+            };
+            libc::timespec {
+                tv_sec: -secs,
+                tv_nsec: nanos as libc::c_long,
+            }
+        }
+    }
+}
+
+pub fn seek(fd: BorrowedFd, offset: u64, whence: c_int) -> Result<u64, Error> {
+    let ret = unsafe { libc::lseek(fd.as_raw_fd(), offset as libc::off_t, whence) };
     if ret < 0 {
         return Err(Error::last_os_error());
     }
-    Ok(())
+    Ok(ret as u64)
 }
 
-pub fn lchown(path: &CStr, uid: uid_t, gid: gid_t) -> Result<(), Error> {
-    let ret = unsafe { libc::lchown(path.as_ptr(), uid, gid) };
+pub fn lchown_at(dirfd: BorrowedFd, path: &CStr, uid: uid_t, gid: gid_t) -> Result<(), Error> {
+    let ret = unsafe {
+        libc::fchownat(
+            dirfd.as_raw_fd(),
+            path.as_ptr(),
+            uid,
+            gid,
+            AT_SYMLINK_NOFOLLOW,
+        )
+    };
     if ret < 0 {
         return Err(Error::last_os_error());
     }
@@ -62,3 +264,256 @@ pub fn lchown(path: &CStr, uid: uid_t, gid: gid_t) -> Result<(), Error> {
 pub fn path_to_c_string(path: PathBuf) -> Result<CString, Error> {
     Ok(CString::new(path.into_os_string().into_vec())?)
 }
+
+pub fn list_xattrs(path: &CStr) -> Result<Vec<CString>, Error> {
+    let names = read_xattr_buf(|buf, size| unsafe { llistxattr(path, buf, size) })?;
+    Ok(names
+        .split(|b| *b == 0)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| CString::new(name).ok())
+        .collect())
+}
+
+pub fn get_xattr(path: &CStr, name: &CStr) -> Result<Vec<u8>, Error> {
+    read_xattr_buf(|buf, size| unsafe { lgetxattr(path, name, buf, size) })
+}
+
+pub fn set_xattr(path: &CStr, name: &CStr, value: &[u8]) -> Result<(), Error> {
+    let ret = unsafe { lsetxattr(path, name, value) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn set_xattr_fd(fd: BorrowedFd, name: &CStr, value: &[u8]) -> Result<(), Error> {
+    let ret = unsafe { fsetxattr(fd, name, value) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn read_xattr_buf<F>(mut read: F) -> Result<Vec<u8>, Error>
+where
+    F: FnMut(*mut libc::c_void, libc::size_t) -> libc::ssize_t,
+{
+    loop {
+        let size = read(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(Error::last_os_error());
+        }
+        let mut buf = vec![0_u8; size as usize];
+        let size = read(buf.as_mut_ptr().cast(), buf.len());
+        if size < 0 {
+            let error = Error::last_os_error();
+            if error.raw_os_error() == Some(libc::ERANGE) {
+                // the value has grown in the meantime
+                continue;
+            }
+            return Err(error);
+        }
+        buf.truncate(size as usize);
+        return Ok(buf);
+    }
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn llistxattr(path: &CStr, buf: *mut libc::c_void, size: libc::size_t) -> libc::ssize_t {
+    libc::llistxattr(path.as_ptr(), buf.cast(), size)
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn lgetxattr(
+    path: &CStr,
+    name: &CStr,
+    buf: *mut libc::c_void,
+    size: libc::size_t,
+) -> libc::ssize_t {
+    libc::lgetxattr(path.as_ptr(), name.as_ptr(), buf, size)
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn lsetxattr(path: &CStr, name: &CStr, value: &[u8]) -> c_int {
+    libc::lsetxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        value.as_ptr().cast(),
+        value.len(),
+        0,
+    )
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn fsetxattr(fd: BorrowedFd, name: &CStr, value: &[u8]) -> c_int {
+    libc::fsetxattr(
+        fd.as_raw_fd(),
+        name.as_ptr(),
+        value.as_ptr().cast(),
+        value.len(),
+        0,
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn llistxattr(path: &CStr, buf: *mut libc::c_void, size: libc::size_t) -> libc::ssize_t {
+    libc::listxattr(path.as_ptr(), buf.cast(), size, libc::XATTR_NOFOLLOW)
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn lgetxattr(
+    path: &CStr,
+    name: &CStr,
+    buf: *mut libc::c_void,
+    size: libc::size_t,
+) -> libc::ssize_t {
+    libc::getxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        buf,
+        size,
+        0,
+        libc::XATTR_NOFOLLOW,
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn lsetxattr(path: &CStr, name: &CStr, value: &[u8]) -> c_int {
+    libc::setxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        value.as_ptr().cast(),
+        value.len(),
+        0,
+        libc::XATTR_NOFOLLOW,
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn fsetxattr(fd: BorrowedFd, name: &CStr, value: &[u8]) -> c_int {
+    libc::fsetxattr(
+        fd.as_raw_fd(),
+        name.as_ptr(),
+        value.as_ptr().cast(),
+        value.len(),
+        0,
+        0,
+    )
+}
+
+pub fn user_name(uid: uid_t) -> Result<Option<String>, Error> {
+    get_entry(
+        |entry, buf, len, result| unsafe { libc::getpwuid_r(uid, entry, buf, len, result) },
+        |entry: &libc::passwd| unsafe { CStr::from_ptr(entry.pw_name) }.to_owned(),
+    )
+    .map(|name| name.and_then(|name| name.into_string().ok()))
+}
+
+pub fn group_name(gid: gid_t) -> Result<Option<String>, Error> {
+    get_entry(
+        |entry, buf, len, result| unsafe { libc::getgrgid_r(gid, entry, buf, len, result) },
+        |entry: &libc::group| unsafe { CStr::from_ptr(entry.gr_name) }.to_owned(),
+    )
+    .map(|name| name.and_then(|name| name.into_string().ok()))
+}
+
+/// Get the ID of the user with the specified name.
+///
+/// Returns `None` if there is no such user.
+pub fn user_id(name: &str) -> Result<Option<uid_t>, Error> {
+    let name = CString::new(name)?;
+    get_entry(
+        |entry, buf, len, result| unsafe {
+            libc::getpwnam_r(name.as_ptr(), entry, buf, len, result)
+        },
+        |entry: &libc::passwd| entry.pw_uid,
+    )
+}
+
+/// Get the ID of the group with the specified name.
+///
+/// Returns `None` if there is no such group.
+pub fn group_id(name: &str) -> Result<Option<gid_t>, Error> {
+    let name = CString::new(name)?;
+    get_entry(
+        |entry, buf, len, result| unsafe {
+            libc::getgrnam_r(name.as_ptr(), entry, buf, len, result)
+        },
+        |entry: &libc::group| entry.gr_gid,
+    )
+}
+
+fn get_entry<T, U, F, G>(mut get: F, convert: G) -> Result<Option<U>, Error>
+where
+    F: FnMut(*mut T, *mut libc::c_char, libc::size_t, *mut *mut T) -> c_int,
+    G: FnOnce(&T) -> U,
+{
+    let mut entry = MaybeUninit::<T>::uninit();
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        let mut result = std::ptr::null_mut();
+        let ret = get(entry.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), &mut result);
+        match ret {
+            0 if result.is_null() => return Ok(None),
+            0 => return Ok(Some(convert(unsafe { &*result }))),
+            libc::ERANGE => {
+                let len = buf.len() * 2;
+                buf.resize(len, 0);
+            }
+            // not found
+            libc::ENOENT | libc::ESRCH => return Ok(None),
+            _ => return Err(Error::from_raw_os_error(ret)),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub const ENOATTR: c_int = libc::ENODATA;
+
+#[cfg(not(target_os = "linux"))]
+pub const ENOATTR: c_int = libc::ENOATTR;
+
+#[cfg(target_os = "linux")]
+pub fn get_file_flags(fd: BorrowedFd) -> Result<FileFlags, Error> {
+    let mut attrs: c_int = 0;
+    let ret = unsafe { libc::ioctl(fd.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut attrs) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(FileFlags::from_linux(attrs as u32))
+}
+
+#[cfg(target_os = "linux")]
+pub fn set_file_flags(fd: BorrowedFd, flags: FileFlags) -> Result<(), Error> {
+    let mut attrs: c_int = 0;
+    let ret = unsafe { libc::ioctl(fd.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut attrs) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    attrs |= flags.to_linux() as c_int;
+    let ret = unsafe { libc::ioctl(fd.as_raw_fd(), libc::FS_IOC_SETFLAGS, &attrs) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_file_flags(fd: BorrowedFd) -> Result<FileFlags, Error> {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+    let ret = unsafe { libc::fstat(fd.as_raw_fd(), stat.as_mut_ptr()) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    Ok(FileFlags::from_bits(stat.st_flags as u32))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_file_flags(fd: BorrowedFd, flags: FileFlags) -> Result<(), Error> {
+    let ret = unsafe { libc::fchflags(fd.as_raw_fd(), flags.bits() as _) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use crate::File;
+
+/// Owner overrides and remapping rules.
+///
+/// When archiving, the rules are applied to every file entry.
+/// When extracting, the rules are applied to the archived owner before it is restored (see
+/// [`ArchiveOptions::preserve_owner`](crate::ArchiveOptions::preserve_owner)).
+///
+/// User and group names take precedence over the ids on extraction, i.e. the ids and the names
+/// should be remapped together.
+#[derive(Clone, Debug, Default)]
+pub struct OwnerMap {
+    user: Option<(u32, Option<String>)>,
+    group: Option<(u32, Option<String>)>,
+    uids: HashMap<u32, u32>,
+    gids: HashMap<u32, u32>,
+    users: HashMap<String, String>,
+    groups: HashMap<String, String>,
+}
+
+impl OwnerMap {
+    /// Create new map that doesn't change anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make user `uid` with the optional `name` the owner of every file.
+    ///
+    /// Remapping rules for the users are not applied in this case.
+    pub fn user(mut self, uid: u32, name: Option<String>) -> Self {
+        self.user = Some((uid, name));
+        self
+    }
+
+    /// Make group `gid` with the optional `name` the group of every file.
+    ///
+    /// Remapping rules for the groups are not applied in this case.
+    pub fn group(mut self, gid: u32, name: Option<String>) -> Self {
+        self.group = Some((gid, name));
+        self
+    }
+
+    /// Replace user id `from` with `to`.
+    pub fn map_uid(mut self, from: u32, to: u32) -> Self {
+        self.uids.insert(from, to);
+        self
+    }
+
+    /// Replace group id `from` with `to`.
+    pub fn map_gid(mut self, from: u32, to: u32) -> Self {
+        self.gids.insert(from, to);
+        self
+    }
+
+    /// Replace user name `from` with `to`.
+    pub fn map_user(mut self, from: &str, to: &str) -> Self {
+        self.users.insert(from.into(), to.into());
+        self
+    }
+
+    /// Replace group name `from` with `to`.
+    pub fn map_group(mut self, from: &str, to: &str) -> Self {
+        self.groups.insert(from.into(), to.into());
+        self
+    }
+
+    /// Apply the rules to the file entry.
+    pub fn apply<X>(&self, file: &mut File<X>) {
+        (file.uid, file.user) = self.user_of(file.uid, file.user.as_deref());
+        (file.gid, file.group) = self.group_of(file.gid, file.group.as_deref());
+    }
+
+    /// Get new user id and name.
+    pub(crate) fn user_of(&self, uid: u32, name: Option<&str>) -> (u32, Option<String>) {
+        map(&self.user, &self.uids, &self.users, uid, name)
+    }
+
+    /// Get new group id and name.
+    pub(crate) fn group_of(&self, gid: u32, name: Option<&str>) -> (u32, Option<String>) {
+        map(&self.group, &self.gids, &self.groups, gid, name)
+    }
+}
+
+fn map(
+    owner: &Option<(u32, Option<String>)>,
+    ids: &HashMap<u32, u32>,
+    names: &HashMap<String, String>,
+    id: u32,
+    name: Option<&str>,
+) -> (u32, Option<String>) {
+    if let Some((id, name)) = owner {
+        return (*id, name.clone());
+    }
+    let id = ids.get(&id).copied().unwrap_or(id);
+    let name = name.map(|name| names.get(name).map(String::as_str).unwrap_or(name));
+    (id, name.map(Into::into))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remap() {
+        let map = OwnerMap::new()
+            .map_uid(1000, 0)
+            .map_user("ci", "root")
+            .map_gid(1000, 0);
+        assert_eq!((0, Some("root".into())), map.user_of(1000, Some("ci")));
+        assert_eq!(
+            (1001, Some("other".into())),
+            map.user_of(1001, Some("other"))
+        );
+        assert_eq!((0, None), map.group_of(1000, None));
+        let map = map.user(0, Some("root".into())).group(0, None);
+        assert_eq!((0, Some("root".into())), map.user_of(1001, Some("other")));
+        assert_eq!((0, None), map.group_of(1001, Some("other")));
+    }
+}
@@ -5,54 +5,81 @@ use std::iter::FusedIterator;
 use std::os::unix::fs::MetadataExt;
 use std::path::Path;
 use std::path::PathBuf;
+use std::rc::Rc;
 
 #[derive(Default)]
 pub struct WalkerOptions {
     follow_symlinks: bool,
     cross_device: bool,
+    sort: bool,
 }
 
 impl WalkerOptions {
-    #[allow(unused)]
     pub fn follow_symlinks(mut self, value: bool) -> Self {
         self.follow_symlinks = value;
         self
     }
 
-    #[allow(unused)]
     pub fn cross_device(mut self, value: bool) -> Self {
         self.cross_device = value;
         self
     }
 
+    pub fn sort(mut self, value: bool) -> Self {
+        self.sort = value;
+        self
+    }
+
     pub fn walk<P: AsRef<Path>>(self, root: P) -> Result<Walker, Error> {
-        let root_dev = root.as_ref().metadata()?.dev();
+        let root_metadata = root.as_ref().metadata()?;
         let mut walker = Walker {
             entries: Default::default(),
-            root_dev,
+            next_dir: None,
+            root_dev: root_metadata.dev(),
             follow_symlinks: self.follow_symlinks,
             cross_device: self.cross_device,
+            sort: self.sort,
         };
-        walker.visit_dir(root)?;
+        let ancestors = vec![(root_metadata.dev(), root_metadata.ino())];
+        walker.visit_dir(root.as_ref().to_path_buf(), Rc::new(ancestors))?;
         Ok(walker)
     }
 }
 
 /// Traverse file tree recursively, breadth-first.
 pub struct Walker {
-    entries: VecDeque<Result<DirEntry, Error>>,
+    // (entry, (dev, inode) of the directories that contain the entry)
+    entries: VecDeque<(Result<DirEntry, Error>, Ancestors)>,
+    // The directory that is visited before returning the next entry.
+    next_dir: Option<(PathBuf, Ancestors)>,
     root_dev: u64,
     follow_symlinks: bool,
     cross_device: bool,
+    // visit directory entries in the order of their names
+    sort: bool,
 }
 
+type Ancestors = Rc<Vec<(u64, u64)>>;
+
 impl Walker {
-    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self, Error> {
-        WalkerOptions::default().walk(root)
+    /// Do not descend into the directory that was returned last.
+    pub fn skip_current_dir(&mut self) {
+        self.next_dir = None;
     }
 
-    fn visit_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
-        self.entries.extend(path.as_ref().read_dir()?);
+    fn visit_dir(&mut self, path: PathBuf, ancestors: Ancestors) -> Result<(), Error> {
+        if self.sort {
+            let mut entries = path.read_dir()?.collect::<Result<Vec<_>, _>>()?;
+            entries.sort_unstable_by_key(|entry| entry.file_name());
+            self.entries.extend(
+                entries
+                    .into_iter()
+                    .map(|entry| (Ok(entry), ancestors.clone())),
+            );
+        } else {
+            self.entries
+                .extend(path.read_dir()?.map(|entry| (entry, ancestors.clone())));
+        }
         Ok(())
     }
 }
@@ -61,63 +88,91 @@ impl Iterator for Walker {
     type Item = Result<DirEntry, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let entry = self.entries.pop_front()?;
-            match entry {
-                Ok(entry) => {
-                    let kind = if self.cross_device {
-                        // do not query metadata if cross device traversal is permitted
-                        // (should be faster as entries contain file type information
-                        // most of the time)
-                        match entry.file_type() {
-                            Ok(kind) => kind,
-                            Err(e) => return Some(Err(e)),
-                        }
-                    } else {
-                        let metadata = match entry.metadata() {
-                            Ok(metadata) => metadata,
-                            Err(e) => return Some(Err(e)),
-                        };
-                        if !self.cross_device && metadata.dev() != self.root_dev {
-                            continue;
-                        }
-                        metadata.file_type()
-                    };
-                    let is_dir = if kind.is_dir() {
-                        true
-                    } else if self.follow_symlinks && kind.is_symlink() {
-                        // resolve symlink
-                        entry.path().is_dir()
-                    } else {
-                        false
-                    };
-                    if is_dir {
-                        if let Err(e) = self.visit_dir(entry.path()) {
-                            return Some(Err(e));
-                        }
+        if let Some((dir, ancestors)) = self.next_dir.take() {
+            if let Err(e) = self.visit_dir(dir, ancestors) {
+                return Some(Err(e));
+            }
+        }
+        let (entry, ancestors) = self.entries.pop_front()?;
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => return Some(Err(e)),
+        };
+        let kind = match entry.file_type() {
+            Ok(kind) => kind,
+            Err(e) => return Some(Err(e)),
+        };
+        if kind.is_dir() && !self.follow_symlinks {
+            // mount points are returned but not descended into (like tar does)
+            let same_device = self.cross_device
+                || match entry.metadata() {
+                    Ok(metadata) => metadata.dev() == self.root_dev,
+                    Err(e) => return Some(Err(e)),
+                };
+            if same_device {
+                self.next_dir = Some((entry.path(), ancestors));
+            }
+        } else if self.follow_symlinks && (kind.is_dir() || kind.is_symlink()) {
+            // resolve symlink and do not descend into the directory if it contains
+            // itself (i.e. there is a symlink loop) or if it resides on another file system
+            match entry.path().metadata() {
+                Ok(metadata)
+                    if metadata.is_dir()
+                        && (self.cross_device || metadata.dev() == self.root_dev) =>
+                {
+                    let id = (metadata.dev(), metadata.ino());
+                    if !ancestors.contains(&id) {
+                        let mut ancestors = ancestors.as_ref().clone();
+                        ancestors.push(id);
+                        self.next_dir = Some((entry.path(), Rc::new(ancestors)));
                     }
-                    return Some(Ok(entry));
                 }
-                other => return Some(other),
+                // broken symlink, not a directory or another file system
+                _ => {}
             }
         }
+        Some(Ok(entry))
     }
 }
 
 impl FusedIterator for Walker {}
 
-pub trait Walk {
-    fn walk(&self) -> Result<Walker, Error>;
-}
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
 
-impl Walk for Path {
-    fn walk(&self) -> Result<Walker, Error> {
-        Walker::new(self)
-    }
-}
+    use tempfile::TempDir;
+
+    use super::*;
 
-impl Walk for PathBuf {
-    fn walk(&self) -> Result<Walker, Error> {
-        Walker::new(self)
+    #[test]
+    fn one_file_system() {
+        let workdir = TempDir::new().unwrap();
+        let root = workdir.path();
+        let Ok(other_dir) = TempDir::new_in("/dev/shm") else {
+            return;
+        };
+        if root.metadata().unwrap().dev() == other_dir.path().metadata().unwrap().dev() {
+            return;
+        }
+        std::fs::write(other_dir.path().join("file"), "").unwrap();
+        symlink(other_dir.path(), root.join("symlink")).unwrap();
+        for follow_symlinks in [false, true] {
+            for cross_device in [false, true] {
+                let walker = WalkerOptions::default()
+                    .follow_symlinks(follow_symlinks)
+                    .cross_device(cross_device)
+                    .walk(root)
+                    .unwrap();
+                let paths: Vec<_> = walker.map(|entry| entry.unwrap().path()).collect();
+                assert!(paths.contains(&root.join("symlink")));
+                let descended = paths.contains(&root.join("symlink").join("file"));
+                assert_eq!(
+                    follow_symlinks && cross_device,
+                    descended,
+                    "follow_symlinks = {follow_symlinks}, cross_device = {cross_device}"
+                );
+            }
+        }
     }
 }
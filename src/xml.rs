@@ -1,13 +1,18 @@
 //! Table of contents.
 
 use std::collections::VecDeque;
+use std::ffi::CStr;
 use std::fs::read_link;
 use std::fs::symlink_metadata;
 use std::io::BufReader;
 use std::io::Error;
 use std::io::ErrorKind;
 use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::io::Write;
+use std::os::fd::AsFd;
+use std::os::fd::BorrowedFd;
 use std::path::Path;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -26,13 +31,25 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde::Serializer;
 
+use crate::get_file_flags;
+use crate::get_xattr;
+use crate::group_name;
+use crate::list_xattrs;
+use crate::path_to_c_string;
+use crate::seek;
+use crate::user_name;
+use crate::Acl;
 use crate::Checksum;
 use crate::ChecksumAlgo;
 use crate::Compression;
+use crate::FileFlags;
 use crate::FileMode;
 use crate::FileType;
+use crate::Hasher;
 use crate::Header;
 use crate::Signer;
+use crate::XattrFilter;
+use crate::ENOATTR;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename = "xar")]
@@ -119,6 +136,7 @@ pub struct File<X = ()> {
     ///
     /// This field only stores the last path component.
     /// Child paths are stored in [`children`](File::children).
+    /// Flattened file trees (see [`into_vec`](File::into_vec)) store full archive paths instead.
     pub name: PathBuf,
 
     /// File type.
@@ -169,6 +187,10 @@ pub struct File<X = ()> {
     #[serde(default)]
     pub ctime: Timestamp,
 
+    /// File flags.
+    #[serde(default, skip_serializing_if = "FileFlags::is_empty")]
+    pub flags: FileFlags,
+
     /// Child path components.
     ///
     /// Contains the files if the current file type is a directory, empty otherwise.
@@ -177,11 +199,15 @@ pub struct File<X = ()> {
     pub children: Vec<File<X>>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    data: Option<FileData>,
+    pub(crate) data: Option<FileData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) link: Option<Link>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    link: Option<Link>,
+    pub(crate) device: Option<Device>,
+    #[serde(rename = "ea", default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) ea: Vec<ExtendedAttribute>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    device: Option<Device>,
+    pub(crate) acl: Option<FileAcl>,
 
     /// Extra data.
     ///
@@ -205,6 +231,8 @@ impl<X> File<X> {
     ///   at which the compressed file contents will be stored,
     /// - `extra` is any data that you want to store along the file entry in the table of contents
     ///   (this data will be encoded as XML).
+    ///
+    /// Returns the file entry and its compressed contents.
     #[allow(clippy::too_many_arguments)]
     pub fn new<P1: AsRef<Path>, P2: AsRef<Path>>(
         id: u64,
@@ -216,54 +244,76 @@ impl<X> File<X> {
         offset: u64,
         extra: Option<X>,
     ) -> Result<(Self, Vec<u8>), Error> {
-        use std::os::unix::fs::MetadataExt;
+        let mut archived = Vec::new();
+        let file = Self::new_streaming(
+            id,
+            prefix,
+            path,
+            name,
+            compression,
+            checksum_algo,
+            offset,
+            extra,
+            &mut archived,
+        )?;
+        Ok((file, archived))
+    }
+
+    /// Create new file entry writing its compressed contents to `heap`.
+    ///
+    /// The arguments are the same as in [`File::new`]. The contents are compressed as they are
+    /// read from the file, i.e. the file is never fully loaded into memory. The number of bytes
+    /// written to `heap` is the [`length`](FileData::length) of the file [`data`](File::data);
+    /// nothing is written if the file has no data.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_streaming<P1: AsRef<Path>, P2: AsRef<Path>, W: Write>(
+        id: u64,
+        prefix: P1,
+        path: P2,
+        name: PathBuf,
+        compression: Compression,
+        checksum_algo: ChecksumAlgo,
+        offset: u64,
+        extra: Option<X>,
+        heap: W,
+    ) -> Result<Self, Error> {
         let path = path.as_ref();
-        let prefix = prefix.as_ref();
+        let mut file = Self::from_path(id, prefix.as_ref(), path, name, extra)?;
+        file.user = user_name(file.uid).unwrap_or_default();
+        file.group = group_name(file.gid).unwrap_or_default();
+        file.read_contents(path, compression, checksum_algo, offset, heap)?;
+        Ok(file)
+    }
+
+    /// Create new file entry from the metadata of the file at `path` without reading its
+    /// contents.
+    ///
+    /// User and group names are not looked up.
+    pub(crate) fn from_path(
+        id: u64,
+        prefix: &Path,
+        path: &Path,
+        name: PathBuf,
+        extra: Option<X>,
+    ) -> Result<Self, Error> {
+        use std::os::unix::fs::MetadataExt;
         let metadata = symlink_metadata(path)?;
         let kind: FileType = metadata.file_type().into();
-        let (has_contents, link) = if metadata.is_file() {
-            (true, None)
-        } else if metadata.is_symlink() {
+        let link = if metadata.is_symlink() {
             // resolve symlink
-            let (has_contents, link_kind) = match path.metadata() {
-                Ok(target_meta) => (target_meta.is_file(), SYMLINK_FILE),
-                Err(_) => {
-                    // broken symlink
-                    (false, SYMLINK_BROKEN)
-                }
+            let link_kind = match path.metadata() {
+                Ok(..) => SYMLINK_FILE,
+                // broken symlink
+                Err(_) => SYMLINK_BROKEN,
             };
             let target = read_link(path)?;
             let target = target.strip_prefix(prefix).unwrap_or(target.as_path());
-            let link = Some(Link {
+            Some(Link {
                 kind: link_kind.into(),
                 target: target.to_path_buf(),
-            });
-            (has_contents, link)
-        } else {
-            (false, None)
-        };
-        let contents = if has_contents {
-            std::fs::read(path)?
+            })
         } else {
-            Vec::new()
-        };
-        let (data, archived) = if !contents.is_empty() {
-            let extracted_checksum = checksum_algo.hash(&contents);
-            let mut encoder = compression.encoder(Vec::new())?;
-            encoder.write_all(&contents)?;
-            let archived = encoder.finish()?;
-            let archived_checksum = checksum_algo.hash(&archived);
-            let data = FileData {
-                archived_checksum: archived_checksum.into(),
-                extracted_checksum: extracted_checksum.into(),
-                encoding: compression.into(),
-                size: contents.len() as u64,
-                length: archived.len() as u64,
-                offset,
-            };
-            (Some(data), archived)
-        } else {
-            (None, Vec::new())
+            None
         };
         let file = Self {
             id,
@@ -276,11 +326,12 @@ impl<X> File<X> {
             gid: metadata.gid(),
             user: None,
             group: None,
-            atime: (metadata.atime() as u64).try_into().unwrap_or_default(),
-            mtime: (metadata.mtime() as u64).try_into().unwrap_or_default(),
-            ctime: (metadata.ctime() as u64).try_into().unwrap_or_default(),
+            atime: Timestamp::from_unix(metadata.atime(), metadata.atime_nsec()),
+            mtime: Timestamp::from_unix(metadata.mtime(), metadata.mtime_nsec()),
+            ctime: Timestamp::from_unix(metadata.ctime(), metadata.ctime_nsec()),
+            flags: Default::default(),
             children: Default::default(),
-            data,
+            data: None,
             link,
             device: if matches!(kind, FileType::CharacterSpecial | FileType::BlockSpecial) {
                 let rdev = metadata.rdev() as _;
@@ -291,9 +342,134 @@ impl<X> File<X> {
             } else {
                 None
             },
+            ea: Default::default(),
+            acl: None,
             extra,
         };
-        Ok((file, archived))
+        Ok(file)
+    }
+
+    /// Compress and checksum the contents of the file at `path` writing them to `heap`.
+    ///
+    /// Only regular files and symbolic links to regular files have contents.
+    pub(crate) fn read_contents<W: Write>(
+        &mut self,
+        path: &Path,
+        compression: Compression,
+        checksum_algo: ChecksumAlgo,
+        offset: u64,
+        heap: W,
+    ) -> Result<(), Error> {
+        let has_contents = match self.kind {
+            FileType::File => true,
+            FileType::Symlink => path.metadata().is_ok_and(|metadata| metadata.is_file()),
+            _ => false,
+        };
+        if !has_contents {
+            return Ok(());
+        }
+        let mut file = std::fs::File::open(path)?;
+        if file.metadata()?.len() != 0 {
+            self.data = Some(FileData::encode_file(
+                &mut file,
+                compression,
+                checksum_algo,
+                offset,
+                heap,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Create new file entry without contents and with default metadata.
+    pub(crate) fn empty(id: u64, name: PathBuf, kind: FileType) -> Self {
+        Self {
+            id,
+            name,
+            kind,
+            inode: 0,
+            deviceno: 0,
+            mode: Default::default(),
+            uid: 0,
+            gid: 0,
+            user: None,
+            group: None,
+            atime: Default::default(),
+            mtime: Default::default(),
+            ctime: Default::default(),
+            flags: Default::default(),
+            children: Default::default(),
+            data: None,
+            link: None,
+            device: None,
+            ea: Default::default(),
+            acl: None,
+            extra: None,
+        }
+    }
+
+    /// Read extended attributes of the file at `path`.
+    ///
+    /// Only the attributes selected by `filter` are stored. On Linux only `user.*`,
+    /// `security.*` and `trusted.*` attributes are read; the attributes that can't be read
+    /// because of insufficient privileges are skipped.
+    ///
+    /// The values are compressed and checksummed separately from the file contents.
+    /// `offset` is the offset from the beginning of the heap at which the values will be stored
+    /// (normally right after the file contents).
+    /// Returns the archived values.
+    pub fn read_xattrs<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        filter: &XattrFilter,
+        compression: Compression,
+        checksum_algo: ChecksumAlgo,
+        mut offset: u64,
+    ) -> Result<Vec<u8>, Error> {
+        let path = path_to_c_string(path.as_ref().to_path_buf())?;
+        let mut names = match list_xattrs(&path) {
+            Ok(names) => names,
+            Err(e) if e.kind() == ErrorKind::Unsupported => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        // do not depend on the order in which the file system lists the attributes
+        names.sort_unstable();
+        let mut archived = Vec::new();
+        for c_name in names.into_iter() {
+            let Ok(name) = c_name.to_str() else {
+                continue;
+            };
+            if !is_portable_xattr(name) || !filter.matches(name) {
+                continue;
+            }
+            let value = match get_xattr(&path, &c_name) {
+                Ok(value) => value,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::PermissionDenied | ErrorKind::Unsupported
+                    ) || e.raw_os_error() == Some(ENOATTR) =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e),
+            };
+            let (data, archived_value) =
+                FileData::encode(&value, compression, checksum_algo, offset)?;
+            offset += archived_value.len() as u64;
+            archived.extend(archived_value);
+            self.ea.push(ExtendedAttribute {
+                id: self.ea.len() as u64,
+                name: name.into(),
+                archived_checksum: data.archived_checksum,
+                extracted_checksum: data.extracted_checksum,
+                encoding: data.encoding,
+                offset: data.offset,
+                size: data.size,
+                length: data.length,
+            });
+        }
+        Ok(archived)
     }
 
     /// Flatten the file tree replacing file names with their full archive paths.
@@ -301,13 +477,14 @@ impl<X> File<X> {
         let mut queue = VecDeque::new();
         queue.push_back((PathBuf::new(), self));
         let mut files = Vec::new();
-        while let Some((mut parent, mut file)) = queue.pop_front() {
-            parent.push(&file.name);
+        while let Some((mut path, mut file)) = queue.pop_front() {
+            path.push(&file.name);
             queue.extend(
                 std::mem::take(&mut file.children)
                     .into_iter()
-                    .map(|file| (parent.clone(), file)),
+                    .map(|file| (path.clone(), file)),
             );
+            file.name = path;
             files.push(file);
         }
         files
@@ -331,6 +508,91 @@ impl<X> File<X> {
     pub fn device(&self) -> Option<&Device> {
         self.device.as_ref()
     }
+
+    /// Get extended attributes.
+    pub fn xattrs(&self) -> &[ExtendedAttribute] {
+        &self.ea[..]
+    }
+
+    /// Read POSIX ACLs of the file at `path`.
+    ///
+    /// The ACLs are read from `system.posix_acl_access` and `system.posix_acl_default` extended
+    /// attributes. Does nothing on platforms other than Linux.
+    pub fn read_acl<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        if !cfg!(target_os = "linux") || self.kind == FileType::Symlink {
+            return Ok(());
+        }
+        let path = path_to_c_string(path.as_ref().to_path_buf())?;
+        let read = |name: &CStr| -> Result<Option<Acl>, Error> {
+            match get_xattr(&path, name) {
+                Ok(value) => Ok(Some(Acl::from_xattr(&value)?)),
+                Err(e)
+                    if e.kind() == ErrorKind::Unsupported || e.raw_os_error() == Some(ENOATTR) =>
+                {
+                    Ok(None)
+                }
+                Err(e) => Err(e),
+            }
+        };
+        let access = read(ACL_ACCESS_XATTR)?;
+        let default = if self.kind == FileType::Directory {
+            read(ACL_DEFAULT_XATTR)?
+        } else {
+            None
+        };
+        self.acl = (access.is_some() || default.is_some()).then_some(FileAcl {
+            access,
+            default,
+            invalid: false,
+        });
+        Ok(())
+    }
+
+    /// Read flags of the file at `path`.
+    ///
+    /// The flags are read only for regular files and directories.
+    pub fn read_flags<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        use std::os::unix::fs::OpenOptionsExt;
+        if !matches!(self.kind, FileType::File | FileType::Directory) {
+            return Ok(());
+        }
+        let file = std::fs::File::options()
+            .read(true)
+            .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+            .open(path)?;
+        self.flags = match get_file_flags(file.as_fd()) {
+            Ok(flags) => flags,
+            // the file system doesn't support flags
+            Err(e)
+                if matches!(
+                    e.raw_os_error(),
+                    Some(libc::ENOTTY | libc::ENOTSUP | libc::EINVAL)
+                ) =>
+            {
+                Default::default()
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(())
+    }
+
+    /// Get POSIX ACLs.
+    pub fn acl(&self) -> Option<&FileAcl> {
+        self.acl.as_ref()
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn is_portable_xattr(name: &str) -> bool {
+    // `system.*` attributes (e.g. ACLs) are file system-specific
+    ["user.", "security.", "trusted."]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn is_portable_xattr(_name: &str) -> bool {
+    true
 }
 
 /// Symbolic link information.
@@ -361,6 +623,54 @@ pub struct Device {
     pub minor: u32,
 }
 
+/// POSIX access control lists.
+///
+/// ACLs that can't be parsed are treated as absent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+#[serde(rename = "acl", from = "RawFileAcl")]
+pub struct FileAcl {
+    /// Access ACL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<Acl>,
+
+    /// Default ACL.
+    ///
+    /// Only directories have default ACLs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Acl>,
+
+    // `true` if any of the archived ACLs couldn't be parsed
+    #[serde(skip)]
+    pub(crate) invalid: bool,
+}
+
+#[derive(Deserialize)]
+struct RawFileAcl {
+    #[serde(default)]
+    access: Option<String>,
+    #[serde(default)]
+    default: Option<String>,
+}
+
+impl From<RawFileAcl> for FileAcl {
+    fn from(other: RawFileAcl) -> Self {
+        let mut invalid = false;
+        let mut parse = |acl: Option<String>| {
+            let acl = acl?.parse().ok();
+            invalid |= acl.is_none();
+            acl
+        };
+        let access = parse(other.access);
+        let default = parse(other.default);
+        Self {
+            access,
+            default,
+            invalid,
+        }
+    }
+}
+
 /// Additional file entry data.
 ///
 /// This data is needed to correctly read the file from the archive.
@@ -385,6 +695,237 @@ pub struct FileData {
 
     /// Compressed file size in bytes.
     pub length: u64,
+
+    /// Holes in the uncompressed file contents.
+    ///
+    /// The holes are still stored in the heap as (compressed) zeros, so that other xar
+    /// implementations that don't know about this element extract the same contents. Only the
+    /// extractor is sparse-aware: it skips the zeros that lie within the holes instead of writing
+    /// them.
+    #[serde(rename = "hole", default, skip_serializing_if = "Vec::is_empty")]
+    pub holes: Vec<Hole>,
+}
+
+impl FileData {
+    /// Compress and checksum file contents.
+    ///
+    /// The holes are found via `SEEK_HOLE`/`SEEK_DATA` and are never read from the file, however,
+    /// they are fed to the compressor as zeros, i.e. the archived contents and the checksums don't
+    /// depend on the hole layout. The compressed contents are written to `heap`.
+    fn encode_file<W: Write>(
+        file: &mut std::fs::File,
+        compression: Compression,
+        checksum_algo: ChecksumAlgo,
+        offset: u64,
+        heap: W,
+    ) -> Result<Self, Error> {
+        let size = file.metadata()?.len();
+        let holes = find_holes(file.as_fd(), size)?;
+        let archived = HashingWriter::new(heap, checksum_algo);
+        let mut writer = HashingWriter::new(compression.encoder(archived)?, checksum_algo);
+        let mut pos = 0;
+        let end = Hole {
+            offset: size,
+            length: 0,
+        };
+        for hole in holes.iter().chain(std::iter::once(&end)) {
+            file.seek(SeekFrom::Start(pos))?;
+            let n = std::io::copy(&mut file.take(hole.offset - pos), &mut writer)?;
+            if n != hole.offset - pos {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "the file was truncated while being archived",
+                ));
+            }
+            std::io::copy(&mut std::io::repeat(0).take(hole.length), &mut writer)?;
+            pos = hole.offset + hole.length;
+        }
+        let extracted_checksum = writer.hasher.finalize();
+        let archived = writer.inner.finish()?;
+        let archived_checksum = archived.hasher.finalize();
+        Ok(FileData {
+            archived_checksum: archived_checksum.into(),
+            extracted_checksum: extracted_checksum.into(),
+            encoding: compression.into(),
+            size,
+            length: archived.len,
+            offset,
+            holes,
+        })
+    }
+
+    /// Compress and checksum the contents read from `reader`.
+    ///
+    /// The compressed contents are written to `heap`.
+    pub(crate) fn encode_reader<R: Read, W: Write>(
+        mut reader: R,
+        compression: Compression,
+        checksum_algo: ChecksumAlgo,
+        offset: u64,
+        heap: W,
+    ) -> Result<Self, Error> {
+        let archived = HashingWriter::new(heap, checksum_algo);
+        let mut writer = HashingWriter::new(compression.encoder(archived)?, checksum_algo);
+        let size = std::io::copy(&mut reader, &mut writer)?;
+        let extracted_checksum = writer.hasher.finalize();
+        let archived = writer.inner.finish()?;
+        let archived_checksum = archived.hasher.finalize();
+        Ok(FileData {
+            archived_checksum: archived_checksum.into(),
+            extracted_checksum: extracted_checksum.into(),
+            encoding: compression.into(),
+            size,
+            length: archived.len,
+            offset,
+            holes: Default::default(),
+        })
+    }
+
+    fn encode(
+        contents: &[u8],
+        compression: Compression,
+        checksum_algo: ChecksumAlgo,
+        offset: u64,
+    ) -> Result<(Self, Vec<u8>), Error> {
+        let extracted_checksum = checksum_algo.hash(contents);
+        let mut encoder = compression.encoder(Vec::new())?;
+        encoder.write_all(contents)?;
+        let archived = encoder.finish()?;
+        let archived_checksum = checksum_algo.hash(&archived);
+        let data = FileData {
+            archived_checksum: archived_checksum.into(),
+            extracted_checksum: extracted_checksum.into(),
+            encoding: compression.into(),
+            size: contents.len() as u64,
+            length: archived.len() as u64,
+            offset,
+            holes: Default::default(),
+        };
+        Ok((data, archived))
+    }
+}
+
+/// A range of zero bytes that is not allocated on disk.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+#[serde(rename = "hole")]
+pub struct Hole {
+    /// Hole offset from the start of the file.
+    pub offset: u64,
+
+    /// Hole size in bytes.
+    pub length: u64,
+}
+
+/// Find holes in the first `size` bytes of the file.
+///
+/// Returns no holes if the file system doesn't support `SEEK_HOLE`/`SEEK_DATA`.
+fn find_holes(fd: BorrowedFd, size: u64) -> Result<Vec<Hole>, Error> {
+    let mut holes = Vec::new();
+    let mut offset = 0;
+    while offset < size {
+        let hole_offset = match seek(fd, offset, libc::SEEK_HOLE) {
+            Ok(hole_offset) => hole_offset,
+            Err(e) if e.raw_os_error() == Some(libc::ENXIO) => break,
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        if hole_offset >= size {
+            break;
+        }
+        let data_offset = match seek(fd, hole_offset, libc::SEEK_DATA) {
+            Ok(data_offset) => data_offset.min(size),
+            Err(e) if e.raw_os_error() == Some(libc::ENXIO) => size,
+            Err(e) => return Err(e),
+        };
+        if data_offset <= hole_offset {
+            break;
+        }
+        holes.push(Hole {
+            offset: hole_offset,
+            length: data_offset - hole_offset,
+        });
+        offset = data_offset;
+    }
+    Ok(holes)
+}
+
+// Hashes and counts the bytes written to the inner writer.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Hasher,
+    len: u64,
+}
+
+impl<W> HashingWriter<W> {
+    fn new(inner: W, checksum_algo: ChecksumAlgo) -> Self {
+        Self {
+            inner,
+            hasher: checksum_algo.hasher(),
+            len: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
+
+/// Extended attribute.
+///
+/// The value is stored in the heap the same way as the file contents.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+#[serde(rename = "ea", rename_all = "kebab-case")]
+pub struct ExtendedAttribute {
+    /// Unique id within the file entry.
+    #[serde(rename = "@id")]
+    pub id: u64,
+
+    /// Attribute name.
+    pub name: String,
+
+    /// The hash of the compressed value.
+    pub archived_checksum: FileChecksum,
+
+    /// The hash of the uncompressed value.
+    pub extracted_checksum: FileChecksum,
+
+    /// Compression codec.
+    pub encoding: Encoding,
+
+    /// Value offset from the start of the heap (i.e. from the end of the header).
+    pub offset: u64,
+
+    /// Uncompressed value size in bytes.
+    pub size: u64,
+
+    /// Compressed value size in bytes.
+    pub length: u64,
+}
+
+impl ExtendedAttribute {
+    /// Get the location of the value in the heap.
+    pub(crate) fn data(&self) -> FileData {
+        FileData {
+            archived_checksum: self.archived_checksum.clone(),
+            extracted_checksum: self.extracted_checksum.clone(),
+            encoding: self.encoding.clone(),
+            offset: self.offset,
+            size: self.size,
+            length: self.length,
+            holes: Default::default(),
+        }
+    }
 }
 
 /// Compression codec.
@@ -476,14 +1017,35 @@ pub struct X509Certificate {
 }
 
 /// UNIX timestamp.
+///
+/// Timestamps before the epoch and fractions of a second are supported.
+/// Fractions of a second are serialized only if they are non-zero
+/// (see [`BuilderOptions::sub_second_timestamps`](crate::BuilderOptions::sub_second_timestamps)).
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(try_from = "String", into = "String")]
 pub struct Timestamp(pub SystemTime);
 
+impl Timestamp {
+    /// Create new timestamp from seconds and nanoseconds since the epoch.
+    ///
+    /// The values are interpreted the same way as `struct timespec` fields,
+    /// i.e. `nanos` are added to `secs` even if `secs` is negative.
+    /// Returns the epoch if the resulting time can't be represented.
+    pub fn from_unix(secs: i64, nanos: i64) -> Self {
+        let nanos = Duration::from_nanos(nanos.clamp(0, 999_999_999) as u64);
+        Self::try_from(secs)
+            .ok()
+            .and_then(|t| t.0.checked_add(nanos))
+            .map(Self)
+            .unwrap_or_default()
+    }
+}
+
 impl From<Timestamp> for String {
     fn from(other: Timestamp) -> String {
         let date_time: DateTime<Utc> = other.0.into();
-        date_time.to_rfc3339_opts(SecondsFormat::Secs, true)
+        // fractional seconds are omitted if zero
+        date_time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
     }
 }
 
@@ -507,12 +1069,26 @@ impl TryFrom<u64> for Timestamp {
     }
 }
 
+impl TryFrom<i64> for Timestamp {
+    type Error = Error;
+    fn try_from(other: i64) -> Result<Self, Self::Error> {
+        let t = if other >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(other as u64))
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(other.unsigned_abs()))
+        };
+        Ok(Self(t.ok_or(ErrorKind::InvalidData)?))
+    }
+}
+
 impl Default for Timestamp {
     fn default() -> Self {
         Self(UNIX_EPOCH)
     }
 }
 
+pub(crate) const ACL_ACCESS_XATTR: &CStr = c"system.posix_acl_access";
+pub(crate) const ACL_DEFAULT_XATTR: &CStr = c"system.posix_acl_default";
 const XML_DECLARATION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
 const SYMLINK_BROKEN: &str = "broken";
-const SYMLINK_FILE: &str = "file";
+pub(crate) const SYMLINK_FILE: &str = "file";